@@ -10,12 +10,29 @@ impl Block {
         Block { statements }
     }
 
-    pub fn execute_unscoped(&mut self, state: &mut State) -> Option<Atom> {
-        let mut res = None;
+    /// Whether a `yield` runs as part of this block, not counting ones in
+    /// the bodies of functions declared in it.
+    pub fn yields(&self) -> bool {
+        self.statements.iter().any(|stmt| match stmt {
+            Stmt::Yield(_) => true,
+            Stmt::IfStmt(If {
+                then_block,
+                else_block,
+                ..
+            }) => then_block.yields() || else_block.yields(),
+            Stmt::WhileStmt(While { loop_block, .. }) => loop_block.yields(),
+            Stmt::MatchStmt(Match { arms, .. }) => arms.iter().any(|arm| arm.block.yields()),
+            Stmt::Block(b) => b.yields(),
+            _ => false,
+        })
+    }
+
+    pub fn execute_unscoped(&mut self, state: &mut State) -> Atom {
+        let mut res = Atom::Nil;
 
         for stmt in self.statements.iter().cloned() {
             res = stmt.execute(state);
-            if matches!(res, Some(Atom::Break)) {
+            if matches!(res, Atom::Break) {
                 break;
             }
         }
@@ -23,10 +40,28 @@ impl Block {
         res
     }
 
-    pub fn execute(&mut self, state: &mut State) -> Option<Atom> {
+    pub fn execute(&mut self, state: &mut State) -> Atom {
         state.scopes.push(Scope::default());
         let res = self.execute_unscoped(state);
         state.scopes.pop();
         res
     }
+
+    /// Runs the block like `execute_unscoped`, except that a call to a Slang
+    /// function in tail position only has its arguments evaluated and is
+    /// handed back to the caller to run.
+    pub fn execute_tail(&self, state: &mut State) -> Tail {
+        match self.statements.split_last() {
+            Some((last, rest)) => {
+                for stmt in rest.iter().cloned() {
+                    let res = stmt.execute(state);
+                    if matches!(res, Atom::Break) {
+                        return Tail::Value(res);
+                    }
+                }
+                last.execute_tail(state)
+            }
+            None => Tail::Value(Atom::Nil),
+        }
+    }
 }
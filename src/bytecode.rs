@@ -0,0 +1,124 @@
+use std::convert::TryInto;
+use std::rc::Rc;
+
+use crate::block::Block;
+use crate::eval::atom::Atom;
+use crate::eval::pattern::Pattern;
+use crate::parser::Op;
+
+pub mod compiler;
+pub mod tinyvm;
+pub mod vm;
+
+pub use compiler::compile;
+pub use vm::Vm;
+
+// Operands are indices into the chunk's constant or name tables, or absolute
+// jump targets, so every instruction stays a couple of words wide.
+#[derive(Debug, Clone, Copy)]
+pub enum Instr {
+    Constant(u16),
+    Load(u16),
+    Define(u16),
+    // binds the value on the stack to a pattern from the chunk's table, see
+    // `State::destructure`
+    Destructure(u16),
+    // for `match`: if the value on the stack matches the pattern, pushes a
+    // scope with its bindings, otherwise jumps. The value stays either way
+    MatchPattern(u16, usize),
+    // raises a `NoMatch` error for the value on the stack
+    NoMatch,
+    // the operator is set for compound assignments
+    Assign(u16, Option<Op>),
+    // calls the named builtin on the array at a variable indexed by the
+    // given number of values, then the rest of the arguments. The array is
+    // written back, see `function::place`
+    ModifyPlace(u16, u16, u8, u8),
+    Unary(Op),
+    Binary(Op),
+    Access(u16),
+    MakeArray(u16),
+    MakeTuple(u16),
+    // joins the given number of values into a string
+    Interpolate(u16),
+    Call(u16, u8),
+    // replaces the current frame, leaving its result as the function's
+    TailCall(u16, u8),
+    Print,
+    Pop,
+    SetResult,
+    ClearResult,
+    // moves the result onto the stack, for a `match` used as an expression
+    PushResult,
+    PushScope,
+    PopScope,
+    Jump(usize),
+    // for `&&`/`||`: if the bool on top of the stack decides the result,
+    // jumps and leaves it there, otherwise pops it
+    ShortCircuit(Op, usize),
+    // errors unless the top of the stack is a bool
+    ExpectBool(Op),
+    // anything other than `true` is falsy, same as the tree walker
+    JumpIfFalse(usize),
+    Return,
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Instr>,
+    pub consts: Vec<Atom>,
+    pub names: Vec<String>,
+    pub patterns: Vec<Pattern>,
+}
+
+impl Chunk {
+    pub fn emit(&mut self, instr: Instr) -> usize {
+        self.code.push(instr);
+        self.code.len() - 1
+    }
+
+    pub fn add_const(&mut self, atom: Atom) -> u16 {
+        self.consts.push(atom);
+        (self.consts.len() - 1)
+            .try_into()
+            .expect("too many constants in chunk")
+    }
+
+    pub fn add_pattern(&mut self, pattern: &Pattern) -> u16 {
+        self.patterns.push(pattern.clone());
+        (self.patterns.len() - 1)
+            .try_into()
+            .expect("too many patterns in chunk")
+    }
+
+    pub fn add_name(&mut self, name: &str) -> u16 {
+        let idx = match self.names.iter().position(|n| n == name) {
+            Some(idx) => idx,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        };
+        idx.try_into().expect("too many names in chunk")
+    }
+
+    /// Points a previously emitted jump at the next instruction.
+    pub fn patch_jump(&mut self, at: usize) {
+        let target = self.code.len();
+        match &mut self.code[at] {
+            Instr::Jump(t)
+            | Instr::JumpIfFalse(t)
+            | Instr::ShortCircuit(_, t)
+            | Instr::MatchPattern(_, t) => *t = target,
+            instr => panic!("Tried to patch {:?}", instr),
+        }
+    }
+}
+
+/// A compiled script: the top level chunk plus the body of every function
+/// declared in it, keyed by the function's block.
+#[derive(Debug)]
+pub struct Program {
+    pub main: Chunk,
+    pub functions: Vec<(Rc<Block>, Chunk)>,
+}
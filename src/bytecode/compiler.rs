@@ -0,0 +1,420 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::mem;
+use std::rc::Rc;
+
+use crate::block::Block;
+use crate::eval::atom::{Atom, FunctionCall, FunctionData};
+use crate::eval::function::{builtin, is_builtin, place, Builtin};
+use crate::parser::{Op, S};
+use crate::statement::{Arm, Declaration, If, Match, Stmt, While};
+
+use super::{Chunk, Instr, Program};
+
+type CompileResult<T> = Result<T, Box<dyn Error>>;
+
+struct Loop {
+    scope_depth: usize,
+    breaks: Vec<usize>,
+}
+
+struct Compiler<'p> {
+    chunk: Chunk,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    functions: &'p mut Vec<(Rc<Block>, Chunk)>,
+}
+
+/// Compiles a parsed script. If `scoped` is set the whole script runs in its
+/// own scope, like `Block::execute`.
+pub fn compile(block: &Block, scoped: bool) -> CompileResult<Program> {
+    let mut functions = Vec::new();
+    let main = Compiler::new(&mut functions).finish(block, scoped, false)?;
+    Ok(Program { main, functions })
+}
+
+/// Compiles a function body on its own. The caller is expected to push the
+/// scope holding the arguments, so the body itself runs unscoped.
+pub fn compile_function(block: &Block) -> CompileResult<Program> {
+    let mut functions = Vec::new();
+    let main = Compiler::new(&mut functions).finish(block, false, true)?;
+    Ok(Program { main, functions })
+}
+
+// an operand holding how many values an instruction takes off the stack,
+// which is an error rather than truncated if it doesn't fit
+fn count<T: TryFrom<usize>>(n: usize, what: &str) -> CompileResult<T> {
+    T::try_from(n).map_err(|_| {
+        let max = (1u64 << (8 * mem::size_of::<T>())) - 1;
+        format!("Too many {}: {}, at most {} are supported", what, n, max).into()
+    })
+}
+
+impl<'p> Compiler<'p> {
+    fn new(functions: &'p mut Vec<(Rc<Block>, Chunk)>) -> Self {
+        Compiler {
+            chunk: Chunk::default(),
+            scope_depth: 0,
+            loops: Vec::new(),
+            functions,
+        }
+    }
+
+    /// `tail` marks the end of the block as a function's return position,
+    /// where calls are compiled to `TailCall`s.
+    fn finish(mut self, block: &Block, scoped: bool, tail: bool) -> CompileResult<Chunk> {
+        self.block(block, scoped, tail)?;
+        self.chunk.emit(Instr::Return);
+        Ok(self.chunk)
+    }
+
+    fn emit(&mut self, instr: Instr) -> usize {
+        self.chunk.emit(instr)
+    }
+
+    fn block(&mut self, block: &Block, scoped: bool, tail: bool) -> CompileResult<()> {
+        if scoped {
+            self.emit(Instr::PushScope);
+            self.scope_depth += 1;
+        }
+
+        if block.statements.is_empty() {
+            self.emit(Instr::ClearResult);
+        }
+
+        if let Some((last, rest)) = block.statements.split_last() {
+            for stmt in rest.iter() {
+                self.stmt(stmt, false)?;
+            }
+            self.stmt(last, tail)?;
+        }
+
+        if scoped {
+            self.emit(Instr::PopScope);
+            self.scope_depth -= 1;
+        }
+
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, tail: bool) -> CompileResult<()> {
+        match stmt {
+            Stmt::ExprStmt(S::Atom(Atom::FnCall(FunctionCall { name, args })))
+                if tail && !is_builtin(name) =>
+            {
+                for arg in args.iter() {
+                    self.expr(arg)?;
+                }
+                let name = self.chunk.add_name(name);
+                self.emit(Instr::TailCall(name, count(args.len(), "arguments")?));
+            }
+            Stmt::ExprStmt(expr) => {
+                self.expr(expr)?;
+                self.emit(Instr::SetResult);
+            }
+            Stmt::PrintStmt(expr) => {
+                self.expr(expr)?;
+                self.emit(Instr::Print);
+                self.emit(Instr::ClearResult);
+            }
+            Stmt::Dec(dec) => {
+                self.declaration(dec)?;
+                self.emit(Instr::ClearResult);
+            }
+            Stmt::Destructure(pattern, rhs) => {
+                self.expr(rhs)?;
+                let pattern = self.chunk.add_pattern(pattern);
+                self.emit(Instr::Destructure(pattern));
+                self.emit(Instr::ClearResult);
+            }
+            Stmt::IfStmt(If {
+                cond,
+                then_block,
+                else_block,
+            }) => {
+                self.expr(cond)?;
+                let to_else = self.emit(Instr::JumpIfFalse(0));
+                self.block(then_block, true, tail)?;
+                let to_end = self.emit(Instr::Jump(0));
+                self.chunk.patch_jump(to_else);
+                self.block(else_block, true, tail)?;
+                self.chunk.patch_jump(to_end);
+            }
+            Stmt::WhileStmt(While { cond, loop_block }) => {
+                let start = self.chunk.code.len();
+                self.expr(cond)?;
+                let to_end = self.emit(Instr::JumpIfFalse(0));
+
+                self.loops.push(Loop {
+                    scope_depth: self.scope_depth,
+                    breaks: Vec::new(),
+                });
+                self.block(loop_block, true, false)?;
+                let Loop { breaks, .. } = self.loops.pop().unwrap();
+
+                self.emit(Instr::Jump(start));
+                self.chunk.patch_jump(to_end);
+                for at in breaks {
+                    self.chunk.patch_jump(at);
+                }
+                // however it ends, a loop evaluates to nil
+                self.emit(Instr::ClearResult);
+            }
+            Stmt::MatchStmt(match_data) => self.match_arms(match_data, tail, false)?,
+            Stmt::Block(b) => self.block(b, true, tail)?,
+            Stmt::Break => {
+                let scope_depth = match self.loops.last() {
+                    Some(l) => l.scope_depth,
+                    None => return Err("break outside of a loop".into()),
+                };
+
+                for _ in scope_depth..self.scope_depth {
+                    self.emit(Instr::PopScope);
+                }
+                let at = self.emit(Instr::Jump(0));
+                self.loops.last_mut().unwrap().breaks.push(at);
+            }
+            Stmt::Yield(_) => return Err("yield outside of a function".into()),
+        }
+
+        Ok(())
+    }
+
+    // With `push` set the match is an expression, and the arm's result is
+    // left on the stack. The statements before it set or clear the result
+    // anyway, so it doesn't matter that it goes through the frame's.
+    fn match_arms(&mut self, match_data: &Match, tail: bool, push: bool) -> CompileResult<()> {
+        let Match { value, arms } = match_data;
+        self.expr(value)?;
+        let mut to_end = Vec::new();
+        for arm in arms.iter() {
+            to_end.push(self.arm(arm, tail, push)?);
+        }
+        self.emit(Instr::NoMatch);
+        for at in to_end {
+            self.chunk.patch_jump(at);
+        }
+        Ok(())
+    }
+
+    // The value being matched stays on the stack until an arm is picked, so
+    // the next arm can try it if a pattern or guard fails. Returns the jump
+    // to the end of the match.
+    fn arm(&mut self, arm: &Arm, tail: bool, push: bool) -> CompileResult<usize> {
+        let mut to_body = Vec::new();
+        let mut to_next = None;
+        for pattern in arm.patterns.iter() {
+            if let Some(at) = to_next {
+                self.chunk.patch_jump(at);
+            }
+            let pattern = self.chunk.add_pattern(pattern);
+            to_next = Some(self.emit(Instr::MatchPattern(pattern, 0)));
+            to_body.push(self.emit(Instr::Jump(0)));
+        }
+        for at in to_body {
+            self.chunk.patch_jump(at);
+        }
+
+        let to_guard_failed = match &arm.guard {
+            Some(guard) => {
+                self.expr(guard)?;
+                Some(self.emit(Instr::JumpIfFalse(0)))
+            }
+            None => None,
+        };
+
+        self.emit(Instr::Pop);
+        self.scope_depth += 1;
+        self.block(&arm.block, false, tail)?;
+        self.scope_depth -= 1;
+        if push {
+            self.emit(Instr::PushResult);
+        }
+        self.emit(Instr::PopScope);
+        let to_end = self.emit(Instr::Jump(0));
+
+        if let Some(at) = to_guard_failed {
+            self.chunk.patch_jump(at);
+            self.emit(Instr::PopScope);
+        }
+        if let Some(at) = to_next {
+            self.chunk.patch_jump(at);
+        }
+        Ok(to_end)
+    }
+
+    fn declaration(&mut self, dec: &Declaration) -> CompileResult<()> {
+        self.expr(&dec.rhs)?;
+        let name = self.chunk.add_name(&dec.lhs);
+        self.emit(if dec.alias {
+            Instr::Define(name)
+        } else {
+            Instr::Assign(name, dec.op)
+        });
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &S) -> CompileResult<()> {
+        match expr {
+            S::Atom(a) => self.atom(a)?,
+            S::Cons(Op::Access, xs) => match xs.as_slice() {
+                [lhs, S::Atom(field)] => {
+                    self.expr(lhs)?;
+                    let field = self.chunk.add_const(field.clone());
+                    self.emit(Instr::Access(field));
+                }
+                _ => return Err(format!("Unsupported field access {}", expr).into()),
+            },
+            S::Cons(op @ (Op::And | Op::Or), xs) => match xs.as_slice() {
+                [a, b] => {
+                    self.expr(a)?;
+                    let to_end = self.emit(Instr::ShortCircuit(*op, 0));
+                    self.expr(b)?;
+                    self.emit(Instr::ExpectBool(*op));
+                    self.chunk.patch_jump(to_end);
+                }
+                _ => return Err(format!("invalid expr: {}", expr).into()),
+            },
+            S::Cons(Op::Interpolate, parts) => {
+                for part in parts.iter() {
+                    self.expr(part)?;
+                }
+                self.emit(Instr::Interpolate(count(
+                    parts.len(),
+                    "interpolated parts",
+                )?));
+            }
+            S::Cons(op, xs) => match xs.as_slice() {
+                [a] => {
+                    self.expr(a)?;
+                    self.emit(Instr::Unary(*op));
+                }
+                [a, b] => {
+                    self.expr(a)?;
+                    self.expr(b)?;
+                    self.emit(Instr::Binary(*op));
+                }
+                _ => return Err(format!("invalid expr: {}", expr).into()),
+            },
+        }
+
+        Ok(())
+    }
+
+    fn atom(&mut self, atom: &Atom) -> CompileResult<()> {
+        match atom {
+            Atom::Identifier(name) => {
+                let name = self.chunk.add_name(name);
+                self.emit(Instr::Load(name));
+            }
+            Atom::FnCall(FunctionCall { name, args })
+                if matches!(builtin(name), Some(Builtin::Mutating(_)))
+                    && args.first().and_then(place).is_some() =>
+            {
+                let (root, indices) = place(&args[0]).unwrap();
+                for idx in indices.iter() {
+                    self.expr(idx)?;
+                }
+                for arg in args[1..].iter() {
+                    self.expr(arg)?;
+                }
+                let name = self.chunk.add_name(name);
+                let root = self.chunk.add_name(root);
+                self.emit(Instr::ModifyPlace(
+                    name,
+                    root,
+                    count(indices.len(), "indices")?,
+                    count(args.len() - 1, "arguments")?,
+                ));
+            }
+            Atom::FnCall(FunctionCall { name, args }) => {
+                for arg in args.iter() {
+                    self.expr(arg)?;
+                }
+                let name = self.chunk.add_name(name);
+                self.emit(Instr::Call(name, count(args.len(), "arguments")?));
+            }
+            Atom::Match(match_data) => self.match_arms(match_data, false, true)?,
+            Atom::Array(elems) => {
+                for elem in elems.iter() {
+                    self.expr(elem)?;
+                }
+                self.emit(Instr::MakeArray(count(elems.len(), "elements")?));
+            }
+            Atom::Tuple(elems) => {
+                for elem in elems.iter() {
+                    self.expr(elem)?;
+                }
+                self.emit(Instr::MakeTuple(count(elems.len(), "elements")?));
+            }
+            // generators run in the tree walker, see `Vm::call`
+            Atom::Function(FunctionData {
+                generator: false,
+                fn_block,
+                ..
+            }) => {
+                let body = Compiler::new(self.functions).finish(fn_block, false, true)?;
+                self.functions.push((Rc::clone(fn_block), body));
+                let idx = self.chunk.add_const(atom.clone());
+                self.emit(Instr::Constant(idx));
+            }
+            _ => {
+                let idx = self.chunk.add_const(atom.clone());
+                self.emit(Instr::Constant(idx));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod compiler_tests {
+    use super::*;
+    use crate::bytecode::Vm;
+    use crate::parser::{parse_block, Lexer};
+    use crate::scan_tokens;
+    use crate::State;
+
+    fn str_to_block(s: &str) -> Block {
+        parse_block(&mut Lexer::new(scan_tokens(s).unwrap()))
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        assert!(compile(&str_to_block("let x = 1\nbreak"), true).is_err());
+        assert!(compile(&str_to_block("while (true) { break }"), true).is_ok());
+    }
+
+    #[test]
+    fn too_many_operands() {
+        let args = vec!["1"; 256].join(", ");
+        let err = compile(&str_to_block(&format!("f({})", args)), true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Too many arguments: 256, at most 255 are supported"
+        );
+
+        let elems = vec!["0"; 65536].join(", ");
+        let err = compile(&str_to_block(&format!("[{}]", elems)), true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Too many elements: 65536, at most 65535 are supported"
+        );
+        assert!(compile(
+            &str_to_block(&format!("f({})", vec!["1"; 255].join(", "))),
+            true
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn call_function_from_state() {
+        // functions declared by the tree walker get compiled on first call
+        let mut state = State::default();
+        str_to_block("fn double(x) { x * 2 }").execute_unscoped(&mut state);
+
+        let program = compile(&str_to_block("double(21)"), true).unwrap();
+        assert_eq!(Vm::new(&mut state).run(program), Atom::Int(42));
+    }
+}
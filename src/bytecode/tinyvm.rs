@@ -0,0 +1,347 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+
+use crate::block::Block;
+use crate::eval::atom::Atom;
+use crate::parser::{Op, S};
+use crate::statement::{Declaration, If, Stmt, While};
+
+type EmitResult<T> = Result<T, Box<dyn Error>>;
+
+// Only the integer/if/loop subset of the language maps onto TinyVM. Every
+// expression leaves its value in eax, using the TinyVM stack for temporaries,
+// and every variable gets its own word of memory.
+
+fn unsupported<T>(construct: &str) -> EmitResult<T> {
+    Err(format!("The TinyVM backend doesn't support {}", construct).into())
+}
+
+/// Compiles a script into TinyVM assembly. Like running the script, the
+/// value of a trailing expression gets printed.
+pub fn emit(block: &Block) -> EmitResult<String> {
+    let mut emitter = Emitter {
+        out: String::new(),
+        scopes: vec![BTreeMap::new()],
+        slots: 0,
+        labels: 0,
+        loop_ends: Vec::new(),
+    };
+
+    emitter.label("start");
+    match block.statements.split_last() {
+        Some((Stmt::ExprStmt(last), rest)) => {
+            emitter.stmts(rest)?;
+            emitter.expr(last)?;
+            emitter.instr("prn eax");
+        }
+        _ => emitter.stmts(&block.statements)?,
+    }
+
+    Ok(emitter.out)
+}
+
+struct Emitter {
+    out: String,
+    scopes: Vec<BTreeMap<String, usize>>,
+    slots: usize,
+    labels: usize,
+    loop_ends: Vec<String>,
+}
+
+impl Emitter {
+    fn instr(&mut self, instr: &str) {
+        writeln!(self.out, "\t{}", instr).unwrap();
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.out, "{}:", label).unwrap();
+    }
+
+    fn new_label(&mut self, name: &str) -> String {
+        self.labels += 1;
+        format!("{}_{}", name, self.labels)
+    }
+
+    fn address(&self, var: &str) -> EmitResult<String> {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(var)) {
+            Some(slot) => Ok(format!("[{:#x}]", slot * 4)),
+            None => Err(format!("Variable {} undefined", var).into()),
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> EmitResult<()> {
+        stmts.iter().try_for_each(|stmt| self.stmt(stmt))
+    }
+
+    fn block(&mut self, block: &Block) -> EmitResult<()> {
+        self.scopes.push(BTreeMap::new());
+        self.stmts(&block.statements)?;
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> EmitResult<()> {
+        match stmt {
+            Stmt::ExprStmt(expr) => self.expr(expr)?,
+            Stmt::PrintStmt(expr) => {
+                self.expr(expr)?;
+                self.instr("prn eax");
+            }
+            Stmt::Dec(dec) => self.declaration(dec)?,
+            Stmt::IfStmt(If {
+                cond,
+                then_block,
+                else_block,
+            }) => {
+                let else_label = self.new_label("else");
+                let end_label = self.new_label("end_if");
+                self.jump_if(cond, false, &else_label)?;
+                self.block(then_block)?;
+                self.instr(&format!("jmp {}", end_label));
+                self.label(&else_label);
+                self.block(else_block)?;
+                self.label(&end_label);
+            }
+            Stmt::WhileStmt(While { cond, loop_block }) => {
+                let start_label = self.new_label("while");
+                let end_label = self.new_label("end_while");
+                self.label(&start_label);
+                self.jump_if(cond, false, &end_label)?;
+                self.loop_ends.push(end_label.clone());
+                self.block(loop_block)?;
+                self.loop_ends.pop();
+                self.instr(&format!("jmp {}", start_label));
+                self.label(&end_label);
+            }
+            Stmt::Block(b) => self.block(b)?,
+            Stmt::Break => match self.loop_ends.last().cloned() {
+                Some(end_label) => self.instr(&format!("jmp {}", end_label)),
+                None => return unsupported("break outside of a loop"),
+            },
+            Stmt::Yield(_) => return unsupported("generators"),
+            Stmt::Destructure(..) => return unsupported("destructuring"),
+            Stmt::MatchStmt(_) => return unsupported("match"),
+        }
+
+        Ok(())
+    }
+
+    fn declaration(&mut self, dec: &Declaration) -> EmitResult<()> {
+        if let S::Atom(Atom::Function(_)) = dec.rhs {
+            return unsupported("function declarations");
+        }
+
+        self.expr(&dec.rhs)?;
+
+        if dec.alias {
+            self.scopes
+                .last_mut()
+                .unwrap()
+                .insert(dec.lhs.clone(), self.slots);
+            self.slots += 1;
+        }
+
+        let address = self.address(&dec.lhs)?;
+        if let Some(op) = dec.op {
+            self.instr("mov ebx, eax");
+            self.instr(&format!("mov eax, {}", address));
+            self.arithmetic(op)?;
+        }
+        self.instr(&format!("mov {}, eax", address));
+
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &S) -> EmitResult<()> {
+        match expr {
+            S::Atom(Atom::Int(n)) => self.instr(&format!("mov eax, {}", n)),
+            S::Atom(Atom::Bool(b)) => self.instr(&format!("mov eax, {}", *b as u8)),
+            S::Atom(Atom::Identifier(name)) => {
+                let address = self.address(name)?;
+                self.instr(&format!("mov eax, {}", address));
+            }
+            S::Atom(Atom::Float(_)) => return unsupported("floats"),
+            #[cfg(feature = "bigint")]
+            S::Atom(Atom::BigInt(_)) => return unsupported("big integers"),
+            S::Atom(Atom::Str(_)) => return unsupported("strings"),
+            S::Atom(Atom::Array(_)) => return unsupported("arrays"),
+            S::Atom(Atom::Tuple(_)) => return unsupported("tuples"),
+            S::Atom(Atom::Range(..)) => return unsupported("ranges"),
+            S::Atom(Atom::Iter(_)) => return unsupported("iterators"),
+            S::Atom(Atom::Nil) => return unsupported("nil"),
+            S::Atom(Atom::FnCall(f)) => {
+                return unsupported(&format!("function calls ({})", f.name))
+            }
+            S::Atom(Atom::Function(_)) => return unsupported("functions"),
+            S::Atom(Atom::Match(_)) => return unsupported("match"),
+            S::Atom(Atom::Break) => return unsupported("break"),
+            S::Cons(Op::Minus, xs) if xs.len() == 1 => {
+                self.expr(&xs[0])?;
+                self.instr("mov ebx, eax");
+                self.instr("mov eax, 0");
+                self.instr("sub eax, ebx");
+            }
+            S::Cons(op @ (Op::Plus | Op::Minus | Op::Multiply | Op::Mod | Op::Divide), xs) => {
+                self.operands(xs)?;
+                self.arithmetic(*op)?;
+            }
+            S::Cons(Op::Interpolate, _) => return unsupported("strings"),
+            S::Cons(Op::Indexing, _) => return unsupported("indexing"),
+            S::Cons(Op::Access, _) => return unsupported("field access"),
+            // boolean valued expressions become 0 or 1
+            S::Cons(
+                Op::Negate
+                | Op::And
+                | Op::Or
+                | Op::Equal
+                | Op::NotEqual
+                | Op::Less
+                | Op::LessEqual
+                | Op::Greater
+                | Op::GreaterEqual,
+                _,
+            ) => {
+                let true_label = self.new_label("true");
+                let end_label = self.new_label("end_bool");
+                self.jump_if(expr, true, &true_label)?;
+                self.instr("mov eax, 0");
+                self.instr(&format!("jmp {}", end_label));
+                self.label(&true_label);
+                self.instr("mov eax, 1");
+                self.label(&end_label);
+            }
+            S::Cons(op, _) => return unsupported(&format!("the {} operator", op)),
+        }
+
+        Ok(())
+    }
+
+    // eax = eax op ebx
+    fn arithmetic(&mut self, op: Op) -> EmitResult<()> {
+        match op {
+            Op::Plus => self.instr("add eax, ebx"),
+            Op::Minus => self.instr("sub eax, ebx"),
+            Op::Multiply => self.instr("mul eax, ebx"),
+            Op::Mod => {
+                self.instr("mod eax, ebx");
+                self.instr("rem eax");
+            }
+            Op::Divide => return unsupported("division"),
+            _ => return unsupported(&format!("the {} operator", op)),
+        }
+        Ok(())
+    }
+
+    // leaves the lhs in eax and the rhs in ebx
+    fn operands(&mut self, xs: &[S]) -> EmitResult<()> {
+        match xs {
+            [a, b] => {
+                self.expr(a)?;
+                self.instr("push eax");
+                self.expr(b)?;
+                self.instr("mov ebx, eax");
+                self.instr("pop eax");
+                Ok(())
+            }
+            _ => unsupported(&format!("{} operands", xs.len())),
+        }
+    }
+
+    /// Jumps to `label` if `cond` evaluates to `sense`, short circuiting
+    /// `&&` and `||`.
+    fn jump_if(&mut self, cond: &S, sense: bool, label: &str) -> EmitResult<()> {
+        match cond {
+            S::Atom(Atom::Bool(b)) => {
+                if *b == sense {
+                    self.instr(&format!("jmp {}", label));
+                }
+            }
+            S::Cons(Op::Negate, xs) if xs.len() == 1 => self.jump_if(&xs[0], !sense, label)?,
+            S::Cons(op @ (Op::And | Op::Or), xs) if xs.len() == 2 => {
+                // `a && b` is false as soon as `a` is, `a || b` is true as soon as `a` is
+                let short_circuit = *op == Op::Or;
+                if sense == short_circuit {
+                    self.jump_if(&xs[0], sense, label)?;
+                    self.jump_if(&xs[1], sense, label)?;
+                } else {
+                    let skip_label = self.new_label("skip");
+                    self.jump_if(&xs[0], short_circuit, &skip_label)?;
+                    self.jump_if(&xs[1], sense, label)?;
+                    self.label(&skip_label);
+                }
+            }
+            S::Cons(
+                op @ (Op::Equal
+                | Op::NotEqual
+                | Op::Less
+                | Op::LessEqual
+                | Op::Greater
+                | Op::GreaterEqual),
+                xs,
+            ) => {
+                self.operands(xs)?;
+                self.instr("cmp eax, ebx");
+                let jump = match (op, sense) {
+                    (Op::Equal, true) | (Op::NotEqual, false) => "je",
+                    (Op::Equal, false) | (Op::NotEqual, true) => "jne",
+                    (Op::Less, true) | (Op::GreaterEqual, false) => "jl",
+                    (Op::Less, false) | (Op::GreaterEqual, true) => "jge",
+                    (Op::Greater, true) | (Op::LessEqual, false) => "jg",
+                    _ => "jle",
+                };
+                self.instr(&format!("{} {}", jump, label));
+            }
+            _ => {
+                self.expr(cond)?;
+                self.instr("cmp eax, 0");
+                self.instr(&format!("{} {}", if sense { "jne" } else { "je" }, label));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tinyvm_tests {
+    use super::*;
+    use crate::parser::{parse_block, Lexer};
+    use crate::scan_tokens;
+
+    fn emit_str(s: &str) -> EmitResult<String> {
+        emit(&parse_block(&mut Lexer::new(scan_tokens(s).unwrap())))
+    }
+
+    #[test]
+    fn project_euler_01_golden() {
+        let code = std::fs::read_to_string("test_files/project_euler_01.slang").unwrap();
+        let expected = std::fs::read_to_string("test_files/project_euler_01.vm").unwrap();
+        assert_eq!(emit_str(&code).unwrap(), expected);
+    }
+
+    #[test]
+    fn unsupported_constructs() {
+        let err = |s| emit_str(s).unwrap_err().to_string();
+        assert_eq!(
+            err("let x = 1.5"),
+            "The TinyVM backend doesn't support floats"
+        );
+        assert_eq!(
+            err("print(\"hi\")"),
+            "The TinyVM backend doesn't support strings"
+        );
+        assert_eq!(
+            err("fn f(x) { x }"),
+            "The TinyVM backend doesn't support function declarations"
+        );
+        assert_eq!(
+            err("let x = len([1])"),
+            "The TinyVM backend doesn't support function calls (len)"
+        );
+        assert_eq!(
+            err("let x = 1 << 2"),
+            "The TinyVM backend doesn't support the << operator"
+        );
+    }
+}
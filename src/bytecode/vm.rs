@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::block::Block;
+use crate::eval::atom::{Atom, FunctionData};
+use crate::eval::error::{raise, RuntimeError};
+use crate::eval::function::{builtin, modify_place, Builtin};
+use crate::eval::iterator::Iter;
+use crate::eval::{binary_op, expect_bool, interpolate, unary_op};
+use crate::parser::{Op, S};
+use crate::statement::{Scope, State};
+
+use super::compiler::compile_function;
+use super::{Chunk, Instr, Program};
+
+struct Frame {
+    chunk: Rc<Chunk>,
+    ip: usize,
+    result: Atom,
+    scope_base: usize,
+}
+
+/// A stack machine for compiled `Program`s. Variables still live in `State`,
+/// so scoping and assignment type checks behave exactly like the tree walker.
+pub struct Vm<'s> {
+    state: &'s mut State,
+    stack: Vec<Atom>,
+    frames: Vec<Frame>,
+    // keyed by the address of the function's block; the Rc is kept alongside
+    // so the address can't be reused while it's in the cache
+    functions: HashMap<*const Block, (Rc<Block>, Rc<Chunk>)>,
+}
+
+impl<'s> Vm<'s> {
+    pub fn new(state: &'s mut State) -> Self {
+        Vm {
+            state,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            functions: HashMap::new(),
+        }
+    }
+
+    pub fn run(&mut self, program: Program) -> Atom {
+        let main = self.load(program);
+        self.frames.push(Frame {
+            chunk: main,
+            ip: 0,
+            result: Atom::Nil,
+            scope_base: self.state.scopes.len(),
+        });
+        self.execute()
+    }
+
+    fn load(&mut self, program: Program) -> Rc<Chunk> {
+        for (block, chunk) in program.functions {
+            self.functions
+                .insert(Rc::as_ptr(&block), (block, Rc::new(chunk)));
+        }
+        Rc::new(program.main)
+    }
+
+    fn function_chunk(&mut self, fn_block: &Rc<Block>) -> Rc<Chunk> {
+        if let Some((_, chunk)) = self.functions.get(&Rc::as_ptr(fn_block)) {
+            return Rc::clone(chunk);
+        }
+
+        // functions which weren't declared in the compiled program, e.g. ones
+        // already in the State, get compiled the first time they're called
+        let program = compile_function(fn_block).unwrap_or_else(|e| panic!("{}", e));
+        let chunk = self.load(program);
+        self.functions.insert(
+            Rc::as_ptr(fn_block),
+            (Rc::clone(fn_block), Rc::clone(&chunk)),
+        );
+        chunk
+    }
+
+    fn pop(&mut self) -> Atom {
+        self.stack.pop().expect("VM stack underflow")
+    }
+
+    fn call(&mut self, name: &str, argc: usize) {
+        let args = self.stack.split_off(self.stack.len() - argc);
+
+        if let Some(builtin) = builtin(name) {
+            let res = builtin.call(&args, self.state);
+            self.stack.push(res);
+            return;
+        }
+
+        let scope_base = self.state.scopes.len();
+        let fn_data = self.function(name);
+        // generator bodies aren't compiled, they run in the tree walker
+        if fn_data.generator {
+            self.stack.push(Iter::generator(name, fn_data, args));
+            return;
+        }
+
+        self.state.enter_call(name);
+        let chunk = self.enter(fn_data, args);
+        self.frames.push(Frame {
+            chunk,
+            ip: 0,
+            result: Atom::Nil,
+            scope_base,
+        });
+    }
+
+    // reuses the current frame, the compiler only emits tail calls for
+    // functions that aren't builtins
+    fn tail_call(&mut self, name: &str, argc: usize) {
+        let args = self.stack.split_off(self.stack.len() - argc);
+        let fn_data = self.function(name);
+
+        // the iterator becomes the current frame's result, which then returns
+        if fn_data.generator {
+            let frame = self.frames.last_mut().unwrap();
+            frame.result = Iter::generator(name, fn_data, args);
+            frame.ip = frame.chunk.code.len() - 1;
+            return;
+        }
+
+        let scope_base = self.frames.last().unwrap().scope_base;
+        self.state.collapse_scopes(scope_base);
+        let chunk = self.enter(fn_data, args);
+
+        let frame = self.frames.last_mut().unwrap();
+        frame.chunk = chunk;
+        frame.ip = 0;
+        frame.result = Atom::Nil;
+    }
+
+    fn function(&self, name: &str) -> FunctionData {
+        match self.state.get_variable(name) {
+            Some(Atom::Function(fn_data)) => fn_data.clone(),
+            _ => panic!("Function {} is undefined", name),
+        }
+    }
+
+    /// Pushes the scope holding the function's arguments and returns its body.
+    fn enter(&mut self, fn_data: FunctionData, args: Vec<Atom>) -> Rc<Chunk> {
+        let FunctionData {
+            arg_names,
+            fn_block,
+            ..
+        } = fn_data;
+
+        let chunk = self.function_chunk(&fn_block);
+        self.state.scopes.push(Scope::default());
+        for (arg_name, arg_val) in arg_names.into_iter().zip(args) {
+            self.state.define(arg_name, arg_val);
+        }
+        chunk
+    }
+
+    fn execute(&mut self) -> Atom {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let chunk = Rc::clone(&frame.chunk);
+            let instr = chunk.code[frame.ip];
+            frame.ip += 1;
+
+            match instr {
+                Instr::Constant(idx) => self.stack.push(chunk.consts[idx as usize].clone()),
+                Instr::Load(name) => {
+                    let name = &chunk.names[name as usize];
+                    match self.state.get_variable(name) {
+                        Some(a) => self.stack.push(a.clone()),
+                        None => panic!("Variable {} undefined in state {:?}", name, self.state),
+                    }
+                }
+                Instr::Define(name) => {
+                    let val = self.pop();
+                    self.state.define(chunk.names[name as usize].clone(), val);
+                }
+                Instr::Destructure(pattern) => {
+                    let val = self.pop();
+                    self.state
+                        .destructure(&chunk.patterns[pattern as usize], val);
+                }
+                Instr::MatchPattern(pattern, target) => {
+                    let mut bindings = Vec::new();
+                    let val = self.stack.last().unwrap();
+                    if chunk.patterns[pattern as usize].matches(val, &mut bindings) {
+                        self.state.scopes.push(Scope::default());
+                        for (var, val) in bindings {
+                            self.state.define(var, val);
+                        }
+                    } else {
+                        self.frames.last_mut().unwrap().ip = target;
+                    }
+                }
+                Instr::NoMatch => {
+                    let value = self.pop().to_string();
+                    raise(RuntimeError::NoMatch { value });
+                }
+                Instr::Assign(name, op) => {
+                    let val = self.pop();
+                    self.state.assign(&chunk.names[name as usize], val, op);
+                }
+                Instr::ModifyPlace(name, root, indices, argc) => {
+                    let args = self.stack.split_off(self.stack.len() - argc as usize);
+                    let indices = self.stack.split_off(self.stack.len() - indices as usize);
+                    let f = match builtin(&chunk.names[name as usize]) {
+                        Some(Builtin::Mutating(f)) => f,
+                        _ => unreachable!(),
+                    };
+                    let root = &chunk.names[root as usize];
+                    let res = modify_place(root, &indices, self.state, |arr| f(arr, &args));
+                    self.stack.push(res);
+                }
+                Instr::Unary(op) => {
+                    let a = self.pop();
+                    self.stack.push(unary_op(&op, a));
+                }
+                Instr::Binary(op) => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push(binary_op(&op, a, b));
+                }
+                Instr::Access(field) => {
+                    let a = self.pop();
+                    let field = S::Atom(chunk.consts[field as usize].clone());
+                    self.stack.push(a.access(&field));
+                }
+                Instr::MakeArray(len) => {
+                    let elems = self.stack.split_off(self.stack.len() - len as usize);
+                    self.stack
+                        .push(Atom::Array(elems.into_iter().map(S::Atom).collect()));
+                }
+                Instr::MakeTuple(len) => {
+                    let elems = self.stack.split_off(self.stack.len() - len as usize);
+                    self.stack
+                        .push(Atom::Tuple(elems.into_iter().map(S::Atom).collect()));
+                }
+                Instr::Interpolate(len) => {
+                    let parts = self.stack.split_off(self.stack.len() - len as usize);
+                    self.stack.push(interpolate(parts.into_iter()));
+                }
+                Instr::Call(name, argc) => self.call(&chunk.names[name as usize], argc as usize),
+                Instr::TailCall(name, argc) => {
+                    self.tail_call(&chunk.names[name as usize], argc as usize)
+                }
+                Instr::Print => println!("{}", self.pop()),
+                Instr::Pop => {
+                    self.pop();
+                }
+                Instr::SetResult => {
+                    let val = self.pop();
+                    self.frames.last_mut().unwrap().result = val;
+                }
+                Instr::ClearResult => self.frames.last_mut().unwrap().result = Atom::Nil,
+                Instr::PushResult => {
+                    let frame = self.frames.last_mut().unwrap();
+                    let val = std::mem::replace(&mut frame.result, Atom::Nil);
+                    self.stack.push(val);
+                }
+                Instr::PushScope => self.state.scopes.push(Scope::default()),
+                Instr::PopScope => {
+                    self.state.scopes.pop();
+                }
+                Instr::Jump(target) => self.frames.last_mut().unwrap().ip = target,
+                Instr::ShortCircuit(op, target) => {
+                    let short_circuit = op == Op::Or;
+                    if expect_bool(&op, self.stack.last().unwrap().clone()) == short_circuit {
+                        self.frames.last_mut().unwrap().ip = target;
+                    } else {
+                        self.pop();
+                    }
+                }
+                Instr::ExpectBool(op) => {
+                    expect_bool(&op, self.stack.last().unwrap().clone());
+                }
+                Instr::JumpIfFalse(target) => {
+                    if self.pop() != Atom::Bool(true) {
+                        self.frames.last_mut().unwrap().ip = target;
+                    }
+                }
+                Instr::Return => {
+                    let frame = self.frames.pop().unwrap();
+                    self.state.scopes.truncate(frame.scope_base);
+
+                    if self.frames.is_empty() {
+                        return frame.result;
+                    }
+                    self.state.exit_call();
+                    self.stack.push(frame.result);
+                }
+            }
+        }
+    }
+}
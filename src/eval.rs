@@ -2,9 +2,16 @@ use crate::parser::*;
 use crate::State;
 
 pub mod atom;
+#[cfg(feature = "bigint")]
+pub mod bigint;
 use atom::Atom;
 
-mod function;
+pub mod error;
+use error::{raise, RuntimeError};
+
+pub mod function;
+pub mod iterator;
+pub mod pattern;
 
 pub fn eval_expr(expr: &S, state: &mut State) -> Atom {
     let mut eval = |expr: &S| eval_expr(expr, state);
@@ -16,37 +23,91 @@ pub fn eval_expr(expr: &S, state: &mut State) -> Atom {
                     None => panic!("Variable {} undefined in state {:?}", name, state),
                 }
             }
-            Atom::FnCall(f) => function::eval_function_call(f, state).unwrap(),
+            Atom::FnCall(f) => function::eval_function_call(f, state),
+            Atom::Match(m) => {
+                let res = m.enter_arm(state).clone().execute_unscoped(state);
+                state.scopes.pop();
+                res
+            }
             Atom::Array(arr) => {
                 let new_arr = arr.iter().map(|s| S::Atom(eval(s))).collect();
                 Atom::Array(new_arr).clone()
             }
+            Atom::Tuple(elems) => Atom::Tuple(elems.iter().map(|s| S::Atom(eval(s))).collect()),
             _ => a.clone(),
         },
-        S::Cons(op, xs) => {
-            let slice = xs.as_slice();
-            match (op, slice) {
-                (Op::Plus, [a, b, ..]) => eval(&a) + eval(&b),
-                (Op::Minus, [a, b, ..]) => eval(&a) - eval(&b),
-                (Op::Minus, [a]) => eval(&a).negate(),
-                (Op::Multiply, [a, b, ..]) => eval(&a) * eval(&b),
-                (Op::Divide, [a, b, ..]) => eval(&a) / eval(&b),
-                (Op::Negate, [a]) => eval(&a).negate(),
-                (Op::Equal, [a, b]) => Atom::Bool(eval(&a) == (eval(&b))),
-                (Op::NotEqual, [a, b]) => Atom::Bool(eval(&a) != (eval(&b))),
-                (Op::Less, [a, b]) => Atom::Bool(eval(&a) < (eval(&b))),
-                (Op::Greater, [a, b]) => Atom::Bool(eval(&a) > (eval(&b))),
-                (Op::Mod, [a, b]) => eval(&a).modulus(&eval(&b)),
-                (Op::And, [a, b]) => eval(&a).and(&eval(&b)),
-                (Op::Or, [a, b]) => eval(&a).or(&eval(&b)),
-                (Op::Indexing, [a, b]) => {
-                    let a = eval(&a).index(&eval(&b));
-                    eval(&S::Atom(a))
-                },
-                (Op::Access, [a, b]) => eval(&a).access(&b),
-                _ => panic!("invalid expr: {}", expr),
+        S::Cons(op, xs) => match (op, xs.as_slice()) {
+            (Op::Access, [a, b]) => eval(a).access(b),
+            (Op::Interpolate, parts) => interpolate(parts.iter().map(eval)),
+            // the rhs is only evaluated if the lhs doesn't decide the result
+            (Op::And | Op::Or, [a, b]) => {
+                let short_circuit = *op == Op::Or;
+                if expect_bool(op, eval(a)) == short_circuit {
+                    Atom::Bool(short_circuit)
+                } else {
+                    Atom::Bool(expect_bool(op, eval(b)))
+                }
             }
+            (op, [a]) => unary_op(op, eval(a)),
+            (op, [a, b]) => binary_op(op, eval(a), eval(b)),
+            _ => panic!("invalid expr: {}", expr),
+        },
+    }
+}
+
+/// `&&` and `||` don't have a notion of truthiness, both operands have to
+/// be bools.
+pub fn expect_bool(op: &Op, a: Atom) -> bool {
+    match a {
+        Atom::Bool(b) => b,
+        _ => raise(RuntimeError::NotBool {
+            op: *op,
+            value: a.to_string(),
+        }),
+    }
+}
+
+/// Joins the evaluated parts of an interpolated string.
+pub fn interpolate(parts: impl Iterator<Item = Atom>) -> Atom {
+    Atom::Str(parts.map(|part| part.to_string()).collect())
+}
+
+/// Applies a prefix operator to an already evaluated operand. Shared by
+/// the tree walker and the bytecode VM so both agree on semantics.
+pub fn unary_op(op: &Op, a: Atom) -> Atom {
+    match op {
+        Op::Minus | Op::Negate => a.negate(),
+        Op::BitNot => a.bit_not(),
+        _ => panic!("invalid unary operator: {}", op),
+    }
+}
+
+/// Applies an infix operator to already evaluated operands. Evaluating
+/// expressions short circuits `&&` and `||` before getting here.
+pub fn binary_op(op: &Op, a: Atom, b: Atom) -> Atom {
+    match op {
+        Op::Plus => a + b,
+        Op::Minus => a - b,
+        Op::Multiply => a * b,
+        Op::Divide => a / b,
+        Op::Equal => Atom::Bool(a == b),
+        Op::NotEqual => Atom::Bool(a != b),
+        Op::Less => Atom::Bool(a < b),
+        Op::Greater => Atom::Bool(a > b),
+        Op::LessEqual => Atom::Bool(a <= b),
+        Op::GreaterEqual => Atom::Bool(a >= b),
+        Op::Mod => a.modulus(&b),
+        Op::FloorDivide => a.floor_div(&b),
+        Op::Pow => a.pow(&b),
+        Op::BitAnd | Op::BitOr | Op::BitXor | Op::ShiftLeft | Op::ShiftRight => {
+            a.bitwise(op, &b)
         }
+        Op::And => Atom::Bool(expect_bool(op, a) && expect_bool(op, b)),
+        Op::Or => Atom::Bool(expect_bool(op, a) || expect_bool(op, b)),
+        Op::Indexing => a.index(&b),
+        Op::In => Atom::Bool(b.contains(&a)),
+        Op::Range => a.range(&b),
+        _ => panic!("invalid binary operator: {}", op),
     }
 }
 
@@ -60,7 +121,7 @@ mod eval_tests {
         ( $( $input:expr => $expected:expr ),* ) => {
             let mut top_state = State::default();
             $(
-                let expr = parse_expr(&mut Lexer::new(scan_tokens($input)));
+                let expr = parse_expr(&mut Lexer::new(scan_tokens($input).unwrap()));
                 assert_eq!(eval_expr(&expr, &mut top_state), $expected);
             )*
         }
@@ -80,4 +141,225 @@ mod eval_tests {
             "(4.0 * 12.5) + 6.0 / (12.5 + 3.0)" => Atom::Float((4.0 * 12.5) + 6.0 / (12.5 + 3.0))
         );
     }
+
+    #[test]
+    fn test_comparisons() {
+        eval_test!(
+            "3 <= 3" => Atom::Bool(true),
+            "3 >= 4" => Atom::Bool(false),
+            "2 <= 2.5" => Atom::Bool(true),
+            "2.5 >= 2" => Atom::Bool(true),
+            "-1.5 > -1" => Atom::Bool(false),
+            "\"abc\" < \"abd\"" => Atom::Bool(true),
+            "\"b\" >= \"abc\"" => Atom::Bool(true),
+            "\"\" <= \"\"" => Atom::Bool(true),
+            "0.0 / 0.0 <= 1" => Atom::Bool(false),
+            "0.0 / 0.0 >= 1" => Atom::Bool(false),
+            // Ints and Floats are equal exactly when they compare equal
+            "1 <= 1.0" => Atom::Bool(true),
+            "1 >= 1.0" => Atom::Bool(true),
+            "1 == 1.0" => Atom::Bool(true),
+            "1.0 != 1" => Atom::Bool(false),
+            "2 == 2.5" => Atom::Bool(false),
+            "2.5 != 2" => Atom::Bool(true),
+            "0.0 / 0.0 == 0" => Atom::Bool(false),
+            "0 != 0.0 / 0.0" => Atom::Bool(true)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't compare")]
+    fn test_compare_mismatched() {
+        eval_test!("\"1\" <= 1" => Atom::Bool(true));
+    }
+
+    #[test]
+    fn test_strings() {
+        let s = |s: &str| Atom::Str(s.to_string());
+        eval_test!(
+            "\"ab\" * 3" => s("ababab"),
+            "2 * \"ab\"" => s("abab"),
+            "\"ab\" * 0" => s(""),
+            "\"héllo\"[1]" => s("é"),
+            "\"héllo\"[1..4]" => s("éll"),
+            "\"héllo\"[5..5]" => s(""),
+            "len([1, 2, 3, 4][1..3])" => Atom::Int(2),
+            "[1, 2, 3, 4][1..3][1]" => Atom::Int(3),
+            "len(\"héllo\")" => Atom::Int(5),
+            "\"ell\" in \"héllo\"" => Atom::Bool(false),
+            "\"éll\" in \"héllo\"" => Atom::Bool(true),
+            "\"\" in \"\"" => Atom::Bool(true),
+            "3 in [1, 2, 3]" => Atom::Bool(true),
+            "[1, 2] in [[1, 2], [3]]" => Atom::Bool(true),
+            "\"3\" in [1, 2, 3]" => Atom::Bool(false),
+            "3 in 0..3" => Atom::Bool(false),
+            "-1 in -2..0" => Atom::Bool(true),
+            "[\"Bob\", \"alice\"][0] < \"Carol\"" => Atom::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_equality() {
+        // arrays and tuples are equal when their elements are
+        eval_test!(
+            "[1, [2, \"a\"]] == [1, [2, \"a\"]]" => Atom::Bool(true),
+            "[1, 2] == [1, 3]" => Atom::Bool(false),
+            "[1] != [1, 1]" => Atom::Bool(true),
+            "[] == []" => Atom::Bool(true),
+            "(1, [2]) == (1, [2])" => Atom::Bool(true),
+            "[1] == (1,)" => Atom::Bool(false)
+        );
+    }
+
+    #[test]
+    fn test_nil() {
+        eval_test!(
+            "nil" => Atom::Nil,
+            "nil == nil" => Atom::Bool(true),
+            "nil != 0" => Atom::Bool(true),
+            "[nil][0] == nil" => Atom::Bool(true),
+            "\"{nil}\"" => Atom::Str("nil".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't slice héllo by 2..6, its length is 5")]
+    fn test_slice_out_of_bounds() {
+        eval_test!("\"héllo\"[2..6]" => Atom::Str(String::new()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't repeat a string -1 times")]
+    fn test_negative_repeat() {
+        eval_test!("\"ab\" * -1" => Atom::Str(String::new()));
+    }
+
+    #[test]
+    fn test_integer_operators() {
+        eval_test!(
+            "2 ** 10" => Atom::Int(1024),
+            "2 ** 3 ** 2" => Atom::Int(512),
+            "-2 ** 2" => Atom::Int(-4),
+            "2 ** -1" => Atom::Float(0.5),
+            "4 ** 0.5" => Atom::Float(2.0),
+            "7 // 2" => Atom::Int(3),
+            "-7 // 2" => Atom::Int(-4),
+            "7 // -2" => Atom::Int(-4),
+            "-8 // 2" => Atom::Int(-4),
+            "7.5 // 2" => Atom::Float(3.0),
+            "12 & 10" => Atom::Int(8),
+            "12 | 10" => Atom::Int(14),
+            "12 ^ 10" => Atom::Int(6),
+            "~5" => Atom::Int(-6),
+            "1 << 10" => Atom::Int(1024),
+            "-16 >> 2" => Atom::Int(-4),
+            "-1 << 63" => Atom::Int(isize::MIN),
+            "1 ** 5000000000" => Atom::Int(1),
+            "0 ** 5000000000" => Atom::Int(0),
+            "-1 ** 5000000001" => Atom::Int(-1),
+            "(-1) ** 5000000000" => Atom::Int(1),
+            "1 + 2 << 3 & 255 | 1" => Atom::Int(25)
+        );
+    }
+
+    fn eval_error(input: &str) -> RuntimeError {
+        let expr = parse_expr(&mut Lexer::new(scan_tokens(input).unwrap()));
+        error::catch(&mut State::default(), |state| eval_expr(&expr, state)).unwrap_err()
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn test_integer_overflow() {
+        let overflow = |op, operands: &[isize]| RuntimeError::IntegerOverflow {
+            op,
+            operands: operands.to_vec(),
+        };
+        let max = isize::MAX;
+        let min = format!("(-{} - 1)", max);
+
+        assert_eq!(
+            eval_error(&format!("{} + 1", max)),
+            overflow(Op::Plus, &[max, 1])
+        );
+        assert_eq!(
+            eval_error(&format!("-{} - 2", max)),
+            overflow(Op::Minus, &[-max, 2])
+        );
+        assert_eq!(
+            eval_error("3037000500 * 3037000500"),
+            overflow(Op::Multiply, &[3037000500, 3037000500])
+        );
+        assert_eq!(eval_error("2 ** 63"), overflow(Op::Pow, &[2, 63]));
+        assert_eq!(
+            eval_error(&format!("{} // -1", min)),
+            overflow(Op::FloorDivide, &[isize::MIN, -1])
+        );
+        assert_eq!(
+            eval_error(&format!("{} / -1", min)),
+            overflow(Op::Divide, &[isize::MIN, -1])
+        );
+        assert_eq!(
+            eval_error(&format!("-{}", min)),
+            overflow(Op::Minus, &[isize::MIN])
+        );
+        assert_eq!(eval_error("1 << 63"), overflow(Op::ShiftLeft, &[1, 63]));
+        assert_eq!(eval_error("3 << 62"), overflow(Op::ShiftLeft, &[3, 62]));
+        eval_test!(&format!("{} % -1", min) => Atom::Int(0));
+        assert_eq!(
+            eval_error("2 ** 63").to_string(),
+            "Integer overflow evaluating 2 ** 63"
+        );
+        assert_eq!(
+            eval_error(&format!("-{}", min)).to_string(),
+            "Integer overflow evaluating -(-9223372036854775808)"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "bigint")]
+    fn test_bigint() {
+        let big = |s: &str| Atom::BigInt(s.parse().unwrap());
+        eval_test!(
+            "2 ** 64" => big("18446744073709551616"),
+            "-(2 ** 64)" => big("-18446744073709551616"),
+            "2 ** 64 - 2 ** 64 + 1" => Atom::Int(1),
+            "2 ** 64 / 2 ** 32" => Atom::Int(4294967296),
+            "2 ** 64 / 3" => Atom::Float(18446744073709551616.0 / 3.0),
+            "-(2 ** 64) // 3" => Atom::Int(-6148914691236517206),
+            "-(2 ** 63)" => Atom::Int(isize::MIN),
+            "2 ** 64 % 10" => Atom::Int(6),
+            "2 ** 64 * 0.5" => Atom::Float(9223372036854775808.0),
+            "2 ** 64 > 2 ** 63" => Atom::Bool(true),
+            "2 ** 64 > 1.5" => Atom::Bool(true),
+            "2 ** 64 == 18446744073709551616.0" => Atom::Bool(true),
+            "2 ** 64 <= 18446744073709551616.0" => Atom::Bool(true),
+            "2.5 != 2 ** 64" => Atom::Bool(true),
+            "2 ** 64 == 2 ** 64" => Atom::Bool(true),
+            "2 ** 64 != 2 ** 65" => Atom::Bool(true),
+            "3037000500 * 3037000500" => big("9223372037000250000"),
+            "1 << 63" => big("9223372036854775808"),
+            "3 << 62" => big("13835058055282163712"),
+            "(1 << 63) >> 62" => Atom::Int(2),
+            "2 ** 64 | 1" => big("18446744073709551617"),
+            "~(2 ** 64)" => big("-18446744073709551617"),
+            "-(2 ** 64) >> 1" => Atom::Int(isize::MIN),
+            "1 ** (2 ** 64)" => Atom::Int(1),
+            "(-1) ** (2 ** 64 + 1)" => Atom::Int(-1)
+        );
+        assert_eq!(eval_error("2 ** 64 // 0"), RuntimeError::DivisionByZero);
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        assert_eq!(eval_error("1 // 0"), RuntimeError::DivisionByZero);
+        assert_eq!(eval_error("1 % 0"), RuntimeError::DivisionByZero);
+        assert_eq!(
+            eval_error("1 << -1"),
+            RuntimeError::InvalidShift { amount: -1 }
+        );
+        assert_eq!(
+            eval_error("1 >> 64").to_string(),
+            "Can't shift by 64 bits, shifts have to be between 0 and 63"
+        );
+    }
 }
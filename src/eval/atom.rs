@@ -1,19 +1,39 @@
 use crate::block::Block;
+use std::cell::RefCell;
+use std::convert::TryFrom;
 use std::fmt;
 use std::ops::{Add, Div, Mul, Sub};
+use std::rc::Rc;
 
-use crate::parser::S;
+#[cfg(feature = "bigint")]
+use crate::eval::bigint;
+use crate::eval::error::{raise, RuntimeError};
+use crate::eval::iterator::Iter;
+use crate::parser::{Op, S};
+use crate::statement::Match;
 
 #[derive(Debug, Clone)]
 pub enum Atom {
     Str(String),
     Float(f64),
     Int(isize),
+    // an integer that doesn't fit in an Int, see `eval::bigint`
+    #[cfg(feature = "bigint")]
+    BigInt(num_bigint::BigInt),
     Bool(bool),
     Identifier(String),
     FnCall(FunctionCall),
+    // a `match` used as an expression, evaluating to the arm it runs
+    Match(Box<Match>),
     Function(FunctionData),
     Array(Vec<S>),
+    // like an array, but immutable
+    Tuple(Vec<S>),
+    // the integers from the start up to but not including the end
+    Range(isize, isize),
+    Iter(Rc<RefCell<Iter>>),
+    // the value of statements and functions which don't produce one
+    Nil,
     Break,
 }
 
@@ -23,13 +43,55 @@ impl PartialEq for Atom {
             (Atom::Str(a), Atom::Str(b)) => a == b,
             (Atom::Float(a), Atom::Float(b)) => a == b,
             (Atom::Int(a), Atom::Int(b)) => a == b,
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(a), Atom::BigInt(b)) => a == b,
+            // mixed numbers are equal when they compare equal, so `==` agrees
+            // with `<=` and `>=`
+            (Atom::Int(_), Atom::Float(_)) | (Atom::Float(_), Atom::Int(_)) => {
+                self.partial_cmp(rhs) == Some(std::cmp::Ordering::Equal)
+            }
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), Atom::Float(_)) | (Atom::Float(_), Atom::BigInt(_)) => {
+                self.partial_cmp(rhs) == Some(std::cmp::Ordering::Equal)
+            }
             (Atom::Bool(a), Atom::Bool(b)) => a == b,
             (Atom::Identifier(a), Atom::Identifier(b)) => a == b,
+            (Atom::Range(a, b), Atom::Range(c, d)) => (a, b) == (c, d),
+            (Atom::Iter(a), Atom::Iter(b)) => Rc::ptr_eq(a, b),
+            (Atom::Nil, Atom::Nil) => true,
+            (Atom::Array(a), Atom::Array(b)) | (Atom::Tuple(a), Atom::Tuple(b)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b).all(|pair| match pair {
+                        (S::Atom(x), S::Atom(y)) => x == y,
+                        _ => false,
+                    })
+            }
             _ => false,
         }
     }
 }
 
+/// Handles an Int operation whose result doesn't fit in an isize. With the
+/// `bigint` feature it's redone with arbitrary precision, otherwise it's an
+/// error.
+fn overflow(op: Op, operands: &[isize]) -> Atom {
+    #[cfg(feature = "bigint")]
+    return bigint::promote(op, operands);
+
+    #[cfg(not(feature = "bigint"))]
+    raise(RuntimeError::IntegerOverflow {
+        op,
+        operands: operands.to_vec(),
+    })
+}
+
+fn checked(op: Op, a: isize, b: isize, f: fn(isize, isize) -> Option<isize>) -> Atom {
+    match f(a, b) {
+        Some(n) => Atom::Int(n),
+        None => overflow(op, &[a, b]),
+    }
+}
+
 impl Add for Atom {
     type Output = Atom;
 
@@ -43,7 +105,9 @@ impl Add for Atom {
             (Atom::Float(a), Atom::Float(b)) => Atom::Float(a + b),
             (Atom::Int(a), Atom::Float(b)) => Atom::Float(*a as f64 + b),
             (Atom::Float(a), Atom::Int(b)) => Atom::Float(a + *b as f64),
-            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a + b),
+            (Atom::Int(a), Atom::Int(b)) => checked(Op::Plus, *a, *b, isize::checked_add),
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), _) | (_, Atom::BigInt(_)) => bigint::binary(&Op::Plus, &self, &rhs),
             _ => panic!("Add not implemented between {} and {}", self, rhs),
         }
     }
@@ -53,15 +117,14 @@ impl Sub for Atom {
     type Output = Atom;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Atom::Str(_), Atom::Str(_)) => todo!(),
-            (Atom::Str(_), Atom::Float(_)) => todo!(),
-            (Atom::Float(_), Atom::Str(_)) => todo!(),
+        match (&self, &rhs) {
             (Atom::Float(a), Atom::Float(b)) => Atom::Float(a - b),
-            (Atom::Int(a), Atom::Float(b)) => Atom::Float(a as f64 - b),
-            (Atom::Float(a), Atom::Int(b)) => Atom::Float(a - b as f64),
-            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a - b),
-            _ => todo!(),
+            (Atom::Int(a), Atom::Float(b)) => Atom::Float(*a as f64 - b),
+            (Atom::Float(a), Atom::Int(b)) => Atom::Float(a - *b as f64),
+            (Atom::Int(a), Atom::Int(b)) => checked(Op::Minus, *a, *b, isize::checked_sub),
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), _) | (_, Atom::BigInt(_)) => bigint::binary(&Op::Minus, &self, &rhs),
+            _ => panic!("Sub not implemented between {} and {}", self, rhs),
         }
     }
 }
@@ -70,15 +133,22 @@ impl Mul for Atom {
     type Output = Atom;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Atom::Str(_), Atom::Str(_)) => todo!(),
-            (Atom::Str(_), Atom::Float(_)) => todo!(),
-            (Atom::Float(_), Atom::Str(_)) => todo!(),
+        match (&self, &rhs) {
+            (Atom::Str(s), Atom::Int(n)) | (Atom::Int(n), Atom::Str(s)) => {
+                match usize::try_from(*n) {
+                    Ok(n) => Atom::Str(s.repeat(n)),
+                    Err(_) => panic!("Can't repeat a string {} times", n),
+                }
+            }
             (Atom::Float(a), Atom::Float(b)) => Atom::Float(a * b),
-            (Atom::Int(a), Atom::Float(b)) => Atom::Float(a as f64 * b),
-            (Atom::Float(a), Atom::Int(b)) => Atom::Float(a * b as f64),
-            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a * b),
-            _ => todo!(),
+            (Atom::Int(a), Atom::Float(b)) => Atom::Float(*a as f64 * b),
+            (Atom::Float(a), Atom::Int(b)) => Atom::Float(a * *b as f64),
+            (Atom::Int(a), Atom::Int(b)) => checked(Op::Multiply, *a, *b, isize::checked_mul),
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), _) | (_, Atom::BigInt(_)) => {
+                bigint::binary(&Op::Multiply, &self, &rhs)
+            }
+            _ => panic!("Mul not implemented between {} and {}", self, rhs),
         }
     }
 }
@@ -87,37 +157,36 @@ impl Div for Atom {
     type Output = Atom;
 
     fn div(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Atom::Str(_), Atom::Str(_)) => todo!(),
-            (Atom::Str(_), Atom::Float(_)) => todo!(),
-            (Atom::Float(_), Atom::Str(_)) => todo!(),
+        match (&self, &rhs) {
             (Atom::Float(a), Atom::Float(b)) => Atom::Float(a / b),
-            (Atom::Int(a), Atom::Float(b)) => Atom::Float(a as f64 / b),
-            (Atom::Float(a), Atom::Int(b)) => Atom::Float(a / b as f64),
-            (Atom::Int(a), Atom::Int(b)) => {
-                let res = a as f64 / b as f64;
-                if res.fract() != 0.0 {
-                    Atom::Float(res)
-                } else {
-                    Atom::Int(res as isize)
-                }
-            },
-            _ => todo!(),
+            (Atom::Int(a), Atom::Float(b)) => Atom::Float(*a as f64 / b),
+            (Atom::Float(a), Atom::Int(b)) => Atom::Float(a / *b as f64),
+            // exact divisions stay Ints, the wrapping_rem is only there so
+            // isize::MIN / -1 is reported as an overflow
+            (Atom::Int(a), Atom::Int(b)) if *b != 0 && a.wrapping_rem(*b) == 0 => {
+                checked(Op::Divide, *a, *b, isize::checked_div)
+            }
+            (Atom::Int(a), Atom::Int(b)) => Atom::Float(*a as f64 / *b as f64),
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), _) | (_, Atom::BigInt(_)) => bigint::binary(&Op::Divide, &self, &rhs),
+            _ => panic!("Div not implemented between {} and {}", self, rhs),
         }
     }
 }
 
+// Numbers compare by value and strings lexicographically. NaN isn't ordered
+// with anything, so every comparison against it is false.
 impl std::cmp::PartialOrd for Atom {
     fn partial_cmp(&self, rhs: &Self) -> Option<std::cmp::Ordering> {
         match (self, rhs) {
-            (Atom::Str(_), Atom::Str(_)) => todo!(),
-            (Atom::Str(_), Atom::Float(_)) => todo!(),
-            (Atom::Float(_), Atom::Str(_)) => todo!(),
+            (Atom::Str(a), Atom::Str(b)) => a.partial_cmp(b),
             (Atom::Float(a), Atom::Float(b)) => a.partial_cmp(b),
             (Atom::Float(a), Atom::Int(b)) => a.partial_cmp(&(*b as f64)),
             (Atom::Int(a), Atom::Float(b)) => (*a as f64).partial_cmp(b),
             (Atom::Int(a), Atom::Int(b)) => a.partial_cmp(b),
-            _ => todo!(),
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), _) | (_, Atom::BigInt(_)) => bigint::compare(self, rhs),
+            _ => panic!("Can't compare {} with {}", self, rhs),
         }
     }
 }
@@ -128,52 +197,179 @@ impl fmt::Display for Atom {
             Atom::Str(s) => write!(f, "{}", s),
             Atom::Float(n) => write!(f, "{}", n),
             Atom::Int(n) => write!(f, "{}", n),
+            #[cfg(feature = "bigint")]
+            Atom::BigInt(n) => write!(f, "{}", n),
             Atom::Bool(b) => write!(f, "{}", b),
             Atom::Identifier(name) => write!(f, "(Identifier {})", name),
             Atom::Break => write!(f, "Break"),
             Atom::FnCall(FunctionCall { name, args }) => write!(f, "{}({:?})", name, args),
+            Atom::Match(m) => write!(f, "match {} {{ ... }}", m.value),
             Atom::Function(_) => write!(f, "FunctionData"),
             Atom::Array(a) => write!(f, "{:?}", a),
+            Atom::Tuple(t) if t.len() == 1 => write!(f, "({},)", t[0]),
+            Atom::Tuple(t) => {
+                let elems: Vec<String> = t.iter().map(|e| e.to_string()).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            Atom::Range(start, end) => write!(f, "{}..{}", start, end),
+            Atom::Iter(_) => write!(f, "Iterator"),
+            Atom::Nil => write!(f, "nil"),
         }
     }
 }
 
 impl Atom {
+    /// The name of the value's type, Ints and BigInts are both "int".
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Atom::Str(_) => "string",
+            Atom::Float(_) => "float",
+            Atom::Int(_) => "int",
+            #[cfg(feature = "bigint")]
+            Atom::BigInt(_) => "int",
+            Atom::Bool(_) => "bool",
+            Atom::Identifier(_) => "identifier",
+            Atom::FnCall(_) => "function call",
+            Atom::Match(_) => "match",
+            Atom::Function(_) => "function",
+            Atom::Array(_) => "array",
+            Atom::Tuple(_) => "tuple",
+            Atom::Range(..) => "range",
+            Atom::Iter(_) => "iterator",
+            Atom::Nil => "nil",
+            Atom::Break => "break",
+        }
+    }
+
     pub fn negate(self) -> Atom {
         match self {
-            Atom::Str(_) => todo!(),
-            Atom::Float(n) => Atom::Float(-1.0 * n),
-            Atom::Int(n) => Atom::Int(-1 * n),
+            Atom::Float(n) => Atom::Float(-n),
+            Atom::Int(n) => match n.checked_neg() {
+                Some(n) => Atom::Int(n),
+                None => overflow(Op::Minus, &[n]),
+            },
+            #[cfg(feature = "bigint")]
+            Atom::BigInt(_) => bigint::unary(&Op::Minus, &self),
             Atom::Bool(b) => Atom::Bool(!b),
-            _ => todo!(),
+            _ => panic!("Can't negate {}", self),
         }
     }
 
     pub fn modulus(&self, rhs: &Atom) -> Atom {
         match (self, rhs) {
             (Atom::Float(a), Atom::Float(b)) => Atom::Float(a % b),
-            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a % b),
-            _ => todo!(),
+            (Atom::Int(_), Atom::Int(0)) => raise(RuntimeError::DivisionByZero),
+            // isize::MIN % -1 is 0, even though computing it overflows
+            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a.wrapping_rem(*b)),
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), _) | (_, Atom::BigInt(_)) => bigint::binary(&Op::Mod, self, rhs),
+            _ => panic!("Mod not implemented between {} and {}", self, rhs),
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Atom::Int(n) => Some(*n as f64),
+            #[cfg(feature = "bigint")]
+            Atom::BigInt(n) => Some(bigint::to_f64(n)),
+            Atom::Float(n) => Some(*n),
+            _ => None,
         }
     }
 
-    pub fn and(&self, rhs: &Atom) -> Atom {
+    /// Division rounded towards negative infinity, so `-7 // 2` is `-4`.
+    /// Stays an Int when both sides are Ints.
+    pub fn floor_div(&self, rhs: &Atom) -> Atom {
         match (self, rhs) {
-            (Atom::Bool(a), Atom::Bool(b)) => Atom::Bool(*a && *b),
-            _ => todo!(),
+            (Atom::Int(_), Atom::Int(0)) => raise(RuntimeError::DivisionByZero),
+            (Atom::Int(a), Atom::Int(b)) => match a.checked_div(*b) {
+                Some(q) if a % b != 0 && (*a < 0) != (*b < 0) => Atom::Int(q - 1),
+                Some(q) => Atom::Int(q),
+                None => overflow(Op::FloorDivide, &[*a, *b]),
+            },
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), _) | (_, Atom::BigInt(_)) => {
+                bigint::binary(&Op::FloorDivide, self, rhs)
+            }
+            _ => match (self.as_float(), rhs.as_float()) {
+                (Some(a), Some(b)) => Atom::Float((a / b).floor()),
+                _ => panic!("Can't floor divide {} by {}", self, rhs),
+            },
         }
     }
 
-    pub fn or(&self, rhs: &Atom) -> Atom {
+    /// An Int to the power of a non-negative Int is an Int, everything else
+    /// is computed with Floats.
+    pub fn pow(&self, rhs: &Atom) -> Atom {
         match (self, rhs) {
-            (Atom::Bool(a), Atom::Bool(b)) => Atom::Bool(*a || *b),
-            _ => todo!(),
+            (Atom::Int(a), Atom::Int(b)) if *b >= 0 => match (u32::try_from(*b), a) {
+                (Ok(exp), _) => match a.checked_pow(exp) {
+                    Some(n) => Atom::Int(n),
+                    None => overflow(Op::Pow, &[*a, *b]),
+                },
+                // powers of these never grow, however large the exponent
+                (Err(_), 0 | 1) => Atom::Int(*a),
+                (Err(_), -1) => Atom::Int(if b % 2 == 0 { 1 } else { -1 }),
+                (Err(_), _) => panic!("Exponent {} is too large", b),
+            },
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), _) | (_, Atom::BigInt(_)) => bigint::binary(&Op::Pow, self, rhs),
+            _ => match (self.as_float(), rhs.as_float()) {
+                (Some(a), Some(b)) => Atom::Float(a.powf(b)),
+                _ => panic!("Can't raise {} to the power of {}", self, rhs),
+            },
+        }
+    }
+
+    /// `&`, `|`, `^`, `<<` and `>>`, which only work on Ints. `>>` keeps the
+    /// sign, and shifting by less than 0 or more than 63 bits is an error.
+    /// `<<` overflows like arithmetic does when it shifts out any bits.
+    pub fn bitwise(&self, op: &Op, rhs: &Atom) -> Atom {
+        let (a, b) = match (self, rhs) {
+            (Atom::Int(a), Atom::Int(b)) => (*a, *b),
+            #[cfg(feature = "bigint")]
+            (Atom::BigInt(_), Atom::Int(_) | Atom::BigInt(_)) | (Atom::Int(_), Atom::BigInt(_)) => {
+                return bigint::binary(op, self, rhs)
+            }
+            _ => panic!("Can't apply {} to {} and {}", op, self, rhs),
+        };
+
+        let shift = || match u32::try_from(b) {
+            Ok(amount) if amount < isize::BITS => amount,
+            _ => raise(RuntimeError::InvalidShift { amount: b }),
+        };
+
+        match op {
+            Op::BitAnd => Atom::Int(a & b),
+            Op::BitOr => Atom::Int(a | b),
+            Op::BitXor => Atom::Int(a ^ b),
+            Op::ShiftLeft => {
+                let amount = shift();
+                match a << amount {
+                    n if n >> amount == a => Atom::Int(n),
+                    _ => overflow(Op::ShiftLeft, &[a, b]),
+                }
+            }
+            Op::ShiftRight => Atom::Int(a >> shift()),
+            _ => panic!("{} isn't a bitwise operator", op),
         }
     }
 
+    pub fn bit_not(self) -> Atom {
+        match self {
+            Atom::Int(n) => Atom::Int(!n),
+            #[cfg(feature = "bigint")]
+            Atom::BigInt(_) => bigint::unary(&Op::BitNot, &self),
+            _ => panic!("Can't apply ~ to {}", self),
+        }
+    }
+
+    /// Indexing by an Int gets a single element, or a single character of a
+    /// string, and indexing by a Range gets a slice. Strings are indexed by
+    /// Unicode scalar value rather than by byte.
     pub fn index(&self, rhs: &Atom) -> Atom {
         match (self, rhs) {
-            (Atom::Array(a), Atom::Int(i)) if i >= &0 => {
+            (Atom::Array(a) | Atom::Tuple(a), Atom::Int(i)) if i >= &0 => {
                 let i = *i as usize;
                 if i < a.len() {
                     if let S::Atom(v) = &a[i] {
@@ -189,10 +385,60 @@ impl Atom {
                     )
                 }
             }
+            (Atom::Str(s), Atom::Int(i)) if i >= &0 => match s.chars().nth(*i as usize) {
+                Some(c) => Atom::Str(c.to_string()),
+                None => panic!(
+                    "Tried getting {}th character of string with length {}",
+                    i,
+                    s.chars().count()
+                ),
+            },
+            (Atom::Range(start, end), Atom::Int(i)) if i >= &0 => match start.checked_add(*i) {
+                Some(n) if n < *end => Atom::Int(n),
+                _ => panic!("Tried getting {}th element of range {}..{}", i, start, end),
+            },
+            (Atom::Array(a), Atom::Range(start, end)) => {
+                let (start, end) = self.slice_bounds(*start, *end, a.len());
+                Atom::Array(a[start..end].to_vec())
+            }
+            (Atom::Str(s), Atom::Range(start, end)) => {
+                let (start, end) = self.slice_bounds(*start, *end, s.chars().count());
+                Atom::Str(s.chars().skip(start).take(end - start).collect())
+            }
             _ => panic!("Can't index {:?} by {:?}", self, rhs),
         }
     }
 
+    fn slice_bounds(&self, start: isize, end: isize, len: usize) -> (usize, usize) {
+        match (usize::try_from(start), usize::try_from(end)) {
+            (Ok(start), Ok(end)) if start <= end && end <= len => (start, end),
+            _ => panic!(
+                "Can't slice {} by {}..{}, its length is {}",
+                self, start, end, len
+            ),
+        }
+    }
+
+    /// Whether `item` is an element of an array or range, or a substring of
+    /// a string. Used for `item in self`.
+    pub fn contains(&self, item: &Atom) -> bool {
+        match (self, item) {
+            (Atom::Array(a) | Atom::Tuple(a), item) => {
+                a.iter().any(|e| matches!(e, S::Atom(e) if e == item))
+            }
+            (Atom::Str(s), Atom::Str(sub)) => s.contains(sub.as_str()),
+            (Atom::Range(start, end), Atom::Int(n)) => (*start..*end).contains(n),
+            _ => panic!("Can't check whether {} is in {}", item, self),
+        }
+    }
+
+    pub fn range(&self, end: &Atom) -> Atom {
+        match (self, end) {
+            (Atom::Int(start), Atom::Int(end)) => Atom::Range(*start, *end),
+            _ => panic!("Can't make a range from {} to {}", self, end),
+        }
+    }
+
     pub fn access(&self, _rhs: &S) -> Atom {
         todo!();
         // match (self, rhs) {
@@ -216,7 +462,10 @@ impl PartialEq for FunctionCall {
 #[derive(Debug, Clone)]
 pub struct FunctionData {
     pub arg_names: Vec<String>,
-    pub fn_block: Block,
+    pub fn_block: Rc<Block>,
+    // whether the body yields, calling a generator function returns an
+    // iterator instead of running it
+    pub generator: bool,
 }
 
 impl PartialEq for FunctionData {
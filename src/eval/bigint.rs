@@ -0,0 +1,114 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Pow, Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+
+use crate::eval::atom::Atom;
+use crate::eval::error::{raise, RuntimeError};
+use crate::parser::Op;
+
+// A BigInt only ever holds a value that doesn't fit in an isize, so every
+// integer has exactly one representation and Int/BigInt equality just works.
+
+pub fn normalize(n: BigInt) -> Atom {
+    match n.to_isize() {
+        Some(n) => Atom::Int(n),
+        None => Atom::BigInt(n),
+    }
+}
+
+fn to_big(a: &Atom) -> Option<BigInt> {
+    match a {
+        Atom::Int(n) => Some(BigInt::from(*n)),
+        Atom::BigInt(n) => Some(n.clone()),
+        _ => None,
+    }
+}
+
+/// Redoes an Int operation that overflowed with arbitrary precision.
+pub fn promote(op: Op, operands: &[isize]) -> Atom {
+    match *operands {
+        [a] => unary(&op, &Atom::Int(a)),
+        [a, b] => binary(&op, &Atom::Int(a), &Atom::Int(b)),
+        _ => unreachable!(),
+    }
+}
+
+pub fn unary(op: &Op, a: &Atom) -> Atom {
+    match (op, to_big(a)) {
+        (Op::Minus | Op::Negate, Some(n)) => normalize(-n),
+        (Op::BitNot, Some(n)) => normalize(!n),
+        _ => panic!("Can't apply {} to {}", op, a),
+    }
+}
+
+/// Arithmetic where at least one side is an integer too large for an Int.
+/// Mixing with a Float converts the integer to a Float.
+pub fn binary(op: &Op, a: &Atom, b: &Atom) -> Atom {
+    match (to_big(a), to_big(b)) {
+        (Some(x), Some(y)) => big_op(op, x, y),
+        _ => match (op, a.as_float(), b.as_float()) {
+            (_, Some(x), Some(y)) => crate::eval::binary_op(op, Atom::Float(x), Atom::Float(y)),
+            (Op::Plus, _, _) if matches!((a, b), (Atom::Str(_), _) | (_, Atom::Str(_))) => {
+                Atom::Str(format!("{}{}", a, b))
+            }
+            _ => panic!("Can't apply {} to {} and {}", op, a, b),
+        },
+    }
+}
+
+fn big_op(op: &Op, a: BigInt, b: BigInt) -> Atom {
+    match op {
+        Op::Plus => normalize(a + b),
+        Op::Minus => normalize(a - b),
+        Op::Multiply => normalize(a * b),
+        // stays an integer only when the division is exact, like Ints
+        Op::Divide if !b.is_zero() && (&a % &b).is_zero() => normalize(a / b),
+        Op::Divide => Atom::Float(to_f64(&a) / to_f64(&b)),
+        Op::Mod | Op::FloorDivide if b.is_zero() => raise(RuntimeError::DivisionByZero),
+        Op::Mod => normalize(a % b),
+        Op::FloorDivide => normalize(a.div_floor(&b)),
+        Op::Pow if b.is_negative() => Atom::Float(to_f64(&a).powf(to_f64(&b))),
+        Op::Pow => match (b.to_u32(), a.to_i8()) {
+            (Some(exp), _) => normalize(Pow::pow(a, exp)),
+            // powers of these never grow, however large the exponent
+            (None, Some(0 | 1)) => normalize(a),
+            (None, Some(-1)) if b.is_even() => Atom::Int(1),
+            (None, Some(-1)) => Atom::Int(-1),
+            (None, _) => panic!("Exponent {} is too large", b),
+        },
+        // bitwise operators act as if negative numbers were in two's
+        // complement with infinitely many ones in front
+        Op::BitAnd => normalize(a & b),
+        Op::BitOr => normalize(a | b),
+        Op::BitXor => normalize(a ^ b),
+        Op::ShiftLeft | Op::ShiftRight => match b.to_u32() {
+            Some(amount) if amount < isize::BITS && *op == Op::ShiftLeft => normalize(a << amount),
+            Some(amount) if amount < isize::BITS => normalize(a >> amount),
+            _ => match b.to_isize() {
+                Some(amount) => raise(RuntimeError::InvalidShift { amount }),
+                None => panic!("Can't shift by {} bits", b),
+            },
+        },
+        _ => panic!("Can't apply {} to {} and {}", op, a, b),
+    }
+}
+
+pub fn compare(a: &Atom, b: &Atom) -> Option<Ordering> {
+    match (to_big(a), to_big(b)) {
+        (Some(x), Some(y)) => Some(x.cmp(&y)),
+        _ => match (a.as_float(), b.as_float()) {
+            (Some(x), Some(y)) => x.partial_cmp(&y),
+            _ => panic!("Can't compare {} with {}", a, b),
+        },
+    }
+}
+
+pub fn to_f64(n: &BigInt) -> f64 {
+    // only fails for values beyond f64's range, which are infinite anyway
+    n.to_f64().unwrap_or(if n.is_negative() {
+        f64::NEG_INFINITY
+    } else {
+        f64::INFINITY
+    })
+}
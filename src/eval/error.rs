@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::parser::Op;
+use crate::statement::State;
+
+/// Errors raised by a running script. They unwind out of the interpreter
+/// and are turned back into an `Err` by `run`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    StackOverflow {
+        function: String,
+        max_depth: usize,
+    },
+    NotBool {
+        op: Op,
+        value: String,
+    },
+    DivisionByZero,
+    InvalidShift {
+        amount: isize,
+    },
+    InvalidNumber {
+        input: String,
+        expected: &'static str,
+    },
+    // no arm of a `match` matched the value
+    NoMatch {
+        value: String,
+    },
+    // with the `bigint` feature Ints get promoted instead
+    #[cfg(not(feature = "bigint"))]
+    IntegerOverflow {
+        op: Op,
+        operands: Vec<isize>,
+    },
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::StackOverflow {
+                function,
+                max_depth,
+            } => write!(
+                f,
+                "Stack overflow: calling {} exceeded the maximum call depth of {}",
+                function, max_depth
+            ),
+            RuntimeError::NotBool { op, value } => {
+                write!(f, "Expected a bool operand for {}, got {}", op, value)
+            }
+            RuntimeError::DivisionByZero => write!(f, "Integer division by zero"),
+            RuntimeError::InvalidShift { amount } => write!(
+                f,
+                "Can't shift by {} bits, shifts have to be between 0 and {}",
+                amount,
+                isize::BITS - 1
+            ),
+            RuntimeError::InvalidNumber { input, expected } => {
+                write!(f, "Can't parse {:?} as {}", input, expected)
+            }
+            RuntimeError::NoMatch { value } => write!(f, "No match arm for {}", value),
+            #[cfg(not(feature = "bigint"))]
+            RuntimeError::IntegerOverflow { op, operands } => match operands.as_slice() {
+                [a] if *a < 0 => write!(f, "Integer overflow evaluating {}({})", op, a),
+                [a] => write!(f, "Integer overflow evaluating {}{}", op, a),
+                [a, b] => write!(f, "Integer overflow evaluating {} {} {}", a, op, b),
+                _ => write!(f, "Integer overflow evaluating {}", op),
+            },
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+pub fn raise(err: RuntimeError) -> ! {
+    panic::panic_any(err)
+}
+
+/// Runs `f`, turning a `RuntimeError` raised inside it back into an `Err`.
+/// Any scopes and calls the script was in the middle of are dropped from
+/// `state`. Other panics keep unwinding.
+pub fn catch<T>(state: &mut State, f: impl FnOnce(&mut State) -> T) -> Result<T, RuntimeError> {
+    let scopes = state.scopes.len();
+    let call_depth = state.call_depth;
+
+    match panic::catch_unwind(AssertUnwindSafe(|| f(state))) {
+        Ok(res) => Ok(res),
+        Err(payload) => match payload.downcast::<RuntimeError>() {
+            Ok(err) => {
+                state.scopes.truncate(scopes);
+                state.call_depth = call_depth;
+                Err(*err)
+            }
+            Err(payload) => panic::resume_unwind(payload),
+        },
+    }
+}
+
+/// Keeps the default panic message for everything but `RuntimeError`s,
+/// which are reported by whoever catches them.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if info.payload().downcast_ref::<RuntimeError>().is_none() {
+            default_hook(info);
+        }
+    }));
+}
@@ -1,111 +1,219 @@
 use std::convert::TryInto;
 
 use crate::eval::atom::{FunctionCall, FunctionData};
-use crate::statement::Declaration;
-use crate::statement::Stmt;
+use crate::eval::iterator::Iter;
+use crate::parser::S;
+use crate::statement::{Scope, Tail};
 use crate::Atom;
 use crate::State;
-use crate::{block::Block, parser::S};
 
 use super::eval_expr;
 
-pub fn eval_function_call(f: &FunctionCall, state: &mut State) -> Option<Atom> {
+mod array;
+mod higher_order;
+mod iterator;
+mod math;
+mod string;
+
+pub use array::{modify_place, place};
+pub use math::{constants, is_constant};
+
+pub fn eval_function_call(f: &FunctionCall, state: &mut State) -> Atom {
     let FunctionCall { name, args } = f;
 
-    if name == "push" {
-        let res = array_push(&args[0], &args[1], state);
-        return Some(res);
+    match (builtin(name), args.split_first()) {
+        (Some(Builtin::Mutating(f)), Some((arr, rest))) if place(arr).is_some() => {
+            let (root, indices) = place(arr).unwrap();
+            let indices: Vec<Atom> = indices.iter().map(|i| eval_expr(i, state)).collect();
+            let rest: Vec<Atom> = rest.iter().map(|arg| eval_expr(arg, state)).collect();
+            return modify_place(root, &indices, state, |arr| f(arr, &rest));
+        }
+        (Some(builtin), _) => {
+            let args: Vec<Atom> = args.iter().map(|arg| eval_expr(arg, state)).collect();
+            return builtin.call(&args, state);
+        }
+        (None, _) => {}
     }
 
-    if name == "len" {
-        let res = array_len(&args[0], state);
-        return Some(res);
-    }
+    let fn_data = match state.get_variable(name) {
+        Some(Atom::Function(fn_data)) => fn_data.clone(),
+        _ => panic!("Function {} is undefined", name),
+    };
+    let args: Vec<Atom> = args.iter().map(|arg| eval_expr(arg, state)).collect();
+    call_function(name, fn_data, args, state)
+}
 
-    if name == "round" {
-        let res = float_round(&args[0], state);
-        return Some(res);
-    }
+/// Calls a function value from a builtin like `map`, which is named in
+/// errors. Unlike a call in a script, the function has to take exactly the
+/// arguments it's given.
+pub fn call_value(caller: &str, f: &Atom, args: Vec<Atom>, state: &mut State) -> Atom {
+    let fn_data = match f {
+        Atom::Function(fn_data) => fn_data.clone(),
+        _ => panic!("{} expects a function, got {}", caller, f),
+    };
 
-    if name == "floor" {
-        let res = float_floor(&args[0], state);
-        return Some(res);
+    if fn_data.arg_names.len() != args.len() {
+        panic!(
+            "{}'s function takes {} arguments, but is called with {}",
+            caller,
+            fn_data.arg_names.len(),
+            args.len()
+        );
     }
 
-    if let Some(Atom::Function(fn_data)) = state.get_variable(name) {
+    call_function(caller, fn_data, args, state)
+}
+
+/// Calls a Slang function with already evaluated arguments.
+pub fn call_function(
+    name: &str,
+    mut fn_data: FunctionData,
+    mut args: Vec<Atom>,
+    state: &mut State,
+) -> Atom {
+    state.enter_call(name);
+    let base = state.scopes.len();
+
+    // calls in tail position come back here instead of recursing, so
+    // tail recursion doesn't grow the Rust stack
+    loop {
+        if fn_data.generator {
+            state.scopes.truncate(base);
+            state.exit_call();
+            return Iter::generator(name, fn_data, args);
+        }
+
         let FunctionData {
             arg_names,
             fn_block,
+            ..
         } = fn_data;
-        let mut full_block_statements: Vec<Stmt> =
-            Vec::with_capacity(args.len() + fn_block.statements.len());
-        args.iter()
-            .zip(arg_names.iter())
-            .for_each(|(arg_val, arg_name)| {
-                full_block_statements.push(Stmt::Dec(Declaration {
-                    lhs: arg_name.to_string(),
-                    rhs: arg_val.clone(),
-                    alias: true,
-                    plus_or_minus: None,
-                }))
-            });
-
-        full_block_statements.append(&mut fn_block.clone().statements);
-
-        let mut full_block = Block::new(full_block_statements);
-        full_block.execute(state)
-    } else {
-        panic!("Function {} is undefined", name);
-    }
-}
 
-fn array_push(a: &S, e: &S, state: &mut State) -> Atom {
-    match (a, e) {
-        (S::Atom(Atom::Identifier(arr_name)), s) => {
-            let new_val = eval_expr(s, state);
-            if let Some(Atom::Array(a)) = state.get_variable(&arr_name) {
-                // this could be sped up a lot by adding a get mut function
-                // to State
-                let mut new_arr = a.clone();
-                new_arr.push(S::Atom(new_val.clone()));
-                state.declare(Declaration {
-                    lhs: arr_name.to_string(),
-                    rhs: S::Atom(Atom::Array(new_arr)),
-                    alias: false,
-                    plus_or_minus: None,
-                });
-                new_val
-            } else {
-                panic!("{} is not an array or undefined", arr_name);
+        state.scopes.push(Scope::default());
+        for (arg_name, arg_val) in arg_names.into_iter().zip(args) {
+            state.define(arg_name, arg_val);
+        }
+        let res = fn_block.execute_tail(state);
+
+        match res {
+            Tail::Value(res) => {
+                state.scopes.truncate(base);
+                state.exit_call();
+                return res;
+            }
+            Tail::Call(tail_fn, tail_args) => {
+                state.collapse_scopes(base);
+                fn_data = tail_fn;
+                args = tail_args;
             }
         }
-        _ => panic!("Can't push"),
     }
 }
 
-fn array_len(a: &S, state: &mut State) -> Atom {
-    if let S::Atom(Atom::Identifier(arr_name)) = a {
-        if let Some(Atom::Array(a)) = state.get_variable(&arr_name) {
-            return Atom::Int(a.len().try_into().unwrap());
+/// Whether a call to `name` is handled natively rather than by calling a
+/// Slang function.
+pub fn is_builtin(name: &str) -> bool {
+    builtin(name).is_some()
+}
+
+#[derive(Clone, Copy)]
+pub enum Builtin {
+    Pure(fn(&[Atom]) -> Atom),
+    // calls back into Slang functions or advances iterators
+    WithState(fn(&[Atom], &mut State) -> Atom),
+    // changes the array passed as its first argument, which gets written
+    // back when it's a place, see `array::place`
+    Mutating(fn(&mut Vec<S>, &[Atom]) -> Atom),
+}
+
+impl Builtin {
+    pub fn call(self, args: &[Atom], state: &mut State) -> Atom {
+        match self {
+            Builtin::Pure(f) => f(args),
+            Builtin::WithState(f) => f(args, state),
+            // without a place to write it back to, the change is lost
+            Builtin::Mutating(f) => match args.split_first() {
+                Some((Atom::Array(arr), rest)) => f(&mut arr.clone(), rest),
+                _ => panic!("Expected an array, got {:?}", args),
+            },
         }
     }
-    panic!("{:?} is not an array", a)
 }
 
-fn float_round(v: &S, state: &mut State) -> Atom {
-    let f = eval_expr(v, state);
-    match f {
-        Atom::Float(n) => Atom::Int(n.round() as isize),
-        Atom::Int(n) => Atom::Int(n),
-        _ => panic!("{:?} is not a number", v),
-    }
+pub fn builtin(name: &str) -> Option<Builtin> {
+    let f = match name {
+        "push" => Builtin::Mutating(array::push),
+        "pop" => Builtin::Mutating(array::pop),
+        "insert" => Builtin::Mutating(array::insert),
+        "remove" => Builtin::Mutating(array::remove),
+        "sort" => Builtin::WithState(array::sort),
+        "map" => Builtin::WithState(higher_order::map),
+        "filter" => Builtin::WithState(higher_order::filter),
+        "reduce" => Builtin::WithState(higher_order::reduce),
+        "any" => Builtin::WithState(higher_order::any),
+        "all" => Builtin::WithState(higher_order::all),
+        "zip" => Builtin::WithState(higher_order::zip),
+        "enumerate" => Builtin::WithState(higher_order::enumerate),
+        "sum" => Builtin::WithState(higher_order::sum),
+        "iter" => Builtin::WithState(iterator::iter),
+        "next" => Builtin::WithState(iterator::next),
+        "has_next" => Builtin::WithState(iterator::has_next),
+        "collect" => Builtin::WithState(iterator::collect),
+        _ => return pure_builtin(name).map(Builtin::Pure),
+    };
+    Some(f)
+}
+
+fn pure_builtin(name: &str) -> Option<fn(&[Atom]) -> Atom> {
+    let f: fn(&[Atom]) -> Atom = match name {
+        "len" => len,
+        "round" => math::round,
+        "floor" => math::floor,
+        "ceil" => math::ceil,
+        "trunc" => math::trunc,
+        "sqrt" => math::sqrt,
+        "pow" => math::pow,
+        "abs" => math::abs,
+        "min" => math::min,
+        "max" => math::max,
+        "clamp" => math::clamp,
+        "sin" => math::sin,
+        "cos" => math::cos,
+        "atan2" => math::atan2,
+        "hypot" => math::hypot,
+        "exp" => math::exp,
+        "ln" => math::ln,
+        "log10" => math::log10,
+        "split" => string::split,
+        "join" => string::join,
+        "trim" => string::trim,
+        "upper" => string::upper,
+        "lower" => string::lower,
+        "replace" => string::replace,
+        "starts_with" => string::starts_with,
+        "ends_with" => string::ends_with,
+        "find" => string::find,
+        "chars" => string::chars,
+        "to_string" => string::to_string,
+        "parse_int" => string::parse_int,
+        "parse_float" => string::parse_float,
+        "slice" => array::slice,
+        "concat" => array::concat,
+        "reverse" => array::reverse,
+        "contains" => array::contains,
+        "index_of" => array::index_of,
+        "range" => array::range,
+        "fill" => array::fill,
+        _ => return None,
+    };
+    Some(f)
 }
 
-fn float_floor(v: &S, state: &mut State) -> Atom {
-    let f = eval_expr(v, state);
-    match f {
-        Atom::Float(n) => Atom::Int(n.floor() as isize),
-        Atom::Int(n) => Atom::Int(n),
-        _ => panic!("{:?} is not a number", v),
+fn len(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Array(a) | Atom::Tuple(a)] => Atom::Int(a.len().try_into().unwrap()),
+        [Atom::Str(s)] => Atom::Int(s.chars().count().try_into().unwrap()),
+        [Atom::Range(start, end)] => Atom::Int(end.saturating_sub(*start).max(0)),
+        _ => panic!("{:?} is not an array, tuple, string or range", args),
     }
 }
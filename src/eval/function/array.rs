@@ -0,0 +1,337 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::convert::TryInto;
+
+use crate::parser::{Op, S};
+use crate::Atom;
+use crate::State;
+
+use super::call_value;
+
+fn elems(arr: &[S]) -> impl Iterator<Item = &Atom> {
+    arr.iter().map(|e| match e {
+        S::Atom(a) => a,
+        _ => unreachable!(),
+    })
+}
+
+fn array(elems: impl Iterator<Item = Atom>) -> Atom {
+    Atom::Array(elems.map(S::Atom).collect())
+}
+
+fn index(name: &str, i: &Atom, len: usize) -> usize {
+    match i {
+        Atom::Int(i) => match usize::try_from(*i) {
+            Ok(i) if i < len => i,
+            _ => panic!("{} got index {} of an array with length {}", name, i, len),
+        },
+        _ => panic!("{} expects an Int index, got {}", name, i),
+    }
+}
+
+/// Splits a place like `a[i][j]` into its variable and indices, which is what
+/// builtins that change an array need to write it back. Other expressions
+/// aren't places.
+pub fn place(expr: &S) -> Option<(&str, Vec<&S>)> {
+    match expr {
+        S::Atom(Atom::Identifier(name)) => Some((name, Vec::new())),
+        S::Cons(Op::Indexing, xs) => match xs.as_slice() {
+            [arr, idx] => {
+                let (root, mut indices) = place(arr)?;
+                indices.push(idx);
+                Some((root, indices))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Applies `f` to the array at `root[indices[0]][indices[1]]...` and assigns
+/// the changed array back to `root`.
+pub fn modify_place(
+    root: &str,
+    indices: &[Atom],
+    state: &mut State,
+    f: impl FnOnce(&mut Vec<S>) -> Atom,
+) -> Atom {
+    let mut val = match state.get_variable(root) {
+        Some(val) => val.clone(),
+        None => panic!("Variable {} is undefined", root),
+    };
+
+    let mut target = &mut val;
+    for i in indices {
+        target = match target {
+            Atom::Array(arr) => {
+                let i = index("[]", i, arr.len());
+                match &mut arr[i] {
+                    S::Atom(e) => e,
+                    _ => unreachable!(),
+                }
+            }
+            other => panic!("Can't index {} by {}", other, i),
+        };
+    }
+
+    let res = match target {
+        Atom::Array(arr) => f(arr),
+        other => panic!("{} is not an array", other),
+    };
+    state.assign(root, val, None);
+    res
+}
+
+pub fn push(arr: &mut Vec<S>, args: &[Atom]) -> Atom {
+    match args {
+        [val] => {
+            arr.push(S::Atom(val.clone()));
+            val.clone()
+        }
+        _ => panic!("push expects an array and a value, got {:?}", args),
+    }
+}
+
+pub fn pop(arr: &mut Vec<S>, args: &[Atom]) -> Atom {
+    match (arr.pop(), args) {
+        (Some(S::Atom(last)), []) => last,
+        (None, []) => panic!("Can't pop from an empty array"),
+        _ => panic!("pop expects just an array, got {:?}", args),
+    }
+}
+
+pub fn insert(arr: &mut Vec<S>, args: &[Atom]) -> Atom {
+    match args {
+        // inserting at the end is fine
+        [i, val] => {
+            let i = index("insert", i, arr.len() + 1);
+            arr.insert(i, S::Atom(val.clone()));
+            val.clone()
+        }
+        _ => panic!(
+            "insert expects an array, an index and a value, got {:?}",
+            args
+        ),
+    }
+}
+
+pub fn remove(arr: &mut Vec<S>, args: &[Atom]) -> Atom {
+    match args {
+        [i] => match arr.remove(index("remove", i, arr.len())) {
+            S::Atom(removed) => removed,
+            _ => unreachable!(),
+        },
+        _ => panic!("remove expects an array and an index, got {:?}", args),
+    }
+}
+
+pub fn slice(args: &[Atom]) -> Atom {
+    match args {
+        [a, Atom::Int(start), Atom::Int(end)] => a.index(&Atom::Range(*start, *end)),
+        _ => panic!("slice expects an array and two Ints, got {:?}", args),
+    }
+}
+
+pub fn concat(args: &[Atom]) -> Atom {
+    let mut res = Vec::new();
+    for a in args {
+        match a {
+            Atom::Array(arr) => res.extend(arr.iter().cloned()),
+            _ => panic!("concat expects arrays, got {}", a),
+        }
+    }
+    Atom::Array(res)
+}
+
+pub fn reverse(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Array(arr)] => Atom::Array(arr.iter().rev().cloned().collect()),
+        [Atom::Str(s)] => Atom::Str(s.chars().rev().collect()),
+        _ => panic!("reverse expects an array or a string, got {:?}", args),
+    }
+}
+
+pub fn contains(args: &[Atom]) -> Atom {
+    match args {
+        [a, item] => Atom::Bool(a.contains(item)),
+        _ => panic!("contains expects an array and a value, got {:?}", args),
+    }
+}
+
+/// The position of the first element equal to the value, or -1.
+pub fn index_of(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Array(arr), item] => match elems(arr).position(|e| e == item) {
+            Some(i) => Atom::Int(i.try_into().unwrap()),
+            None => Atom::Int(-1),
+        },
+        _ => panic!("index_of expects an array and a value, got {:?}", args),
+    }
+}
+
+/// Sorts in ascending order, or by a comparator function which returns a
+/// negative number, 0 or a positive number like `a - b` would. The sort is
+/// stable.
+pub fn sort(args: &[Atom], state: &mut State) -> Atom {
+    let mut sorted: Vec<Atom> = match args {
+        [Atom::Array(arr), ..] => elems(arr).cloned().collect(),
+        _ => panic!("sort expects an array, got {:?}", args),
+    };
+
+    match args {
+        [_] => sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal)),
+        [_, cmp] => sorted.sort_by(|a, b| {
+            let res = call_value("sort", cmp, vec![a.clone(), b.clone()], state);
+            if res.as_float().is_none() {
+                panic!("sort's comparator has to return a number, got {}", res);
+            }
+            match res.partial_cmp(&Atom::Int(0)) {
+                Some(ordering) => ordering,
+                None => panic!("sort's comparator returned NaN for {} and {}", a, b),
+            }
+        }),
+        _ => panic!(
+            "sort expects an array and optionally a function, got {:?}",
+            args
+        ),
+    }
+
+    array(sorted.into_iter())
+}
+
+/// The Ints from `start` up to but not including `end`, counting by `step`,
+/// which can be negative.
+pub fn range(args: &[Atom]) -> Atom {
+    let (start, end, step) = match args {
+        [Atom::Int(start), Atom::Int(end)] => (*start, *end, 1),
+        [Atom::Int(_), Atom::Int(_), Atom::Int(0)] => panic!("range's step can't be 0"),
+        [Atom::Int(start), Atom::Int(end), Atom::Int(step)] => (*start, *end, *step),
+        _ => panic!("range expects two or three Ints, got {:?}", args),
+    };
+
+    let in_range = move |n: &isize| if step > 0 { *n < end } else { *n > end };
+    let ints = std::iter::successors(Some(start), move |n| n.checked_add(step));
+    array(ints.take_while(in_range).map(Atom::Int))
+}
+
+/// An array of `n` copies of a value.
+pub fn fill(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Int(n), val] => match usize::try_from(*n) {
+            Ok(n) => array(std::iter::repeat_n(val.clone(), n)),
+            Err(_) => panic!("Can't fill an array of length {}", n),
+        },
+        _ => panic!("fill expects a length and a value, got {:?}", args),
+    }
+}
+
+#[cfg(test)]
+mod array_tests {
+    use crate::eval::eval_expr;
+    use crate::parser::{parse_block, parse_expr, Lexer};
+    use crate::{scan_tokens, State};
+
+    fn eval_str(input: &str) -> String {
+        let expr = parse_expr(&mut Lexer::new(scan_tokens(input).unwrap()));
+        eval_expr(&expr, &mut State::default()).to_string()
+    }
+
+    fn run(input: &str) -> String {
+        parse_block(&mut Lexer::new(scan_tokens(input).unwrap()))
+            .execute_unscoped(&mut State::default())
+            .to_string()
+    }
+
+    macro_rules! test_builtins {
+        ( $( $input:expr => $expected:expr ),* ) => {
+            $(
+                assert_eq!(eval_str($input), $expected, "{}", $input);
+            )*
+        }
+    }
+
+    #[test]
+    fn builtins() {
+        test_builtins!(
+            // on something that isn't a variable these act on a copy
+            "pop([1, 2, 3])" => "3",
+            "remove([1, 2, 3], 0)" => "1",
+            "insert([1, 2], 2, 5)" => "5",
+            "push([], 4)" => "4",
+            "join(slice([1, 2, 3, 4], 1, 3), \",\")" => "2,3",
+            "slice(\"héllo\", 0, 2)" => "hé",
+            "join(concat([1], [], [2, 3]), \",\")" => "1,2,3",
+            "join(reverse([1, 2, 3]), \",\")" => "3,2,1",
+            "reverse(\"abc\")" => "cba",
+            "contains([1, \"a\"], \"a\")" => "true",
+            "contains([1, 2], 3)" => "false",
+            "index_of([5, 6, 7], 7)" => "2",
+            "index_of([5, 6, 7], 8)" => "-1",
+            "contains([[1, 2], [3]], [3])" => "true",
+            "contains([(1, [2])], (1, [2]))" => "true",
+            "index_of([[1], [2, 3]], [2, 3])" => "1",
+            "join(sort([3, 1.5, 2]), \",\")" => "1.5,2,3",
+            "join(sort([\"b\", \"c\", \"a\"]), \"\")" => "abc",
+            "join(range(0, 5), \",\")" => "0,1,2,3,4",
+            "join(range(1, 10, 3), \",\")" => "1,4,7",
+            "join(range(5, 0, -2), \",\")" => "5,3,1",
+            "len(range(3, 3))" => "0",
+            "join(fill(3, \"x\"), \"\")" => "xxx",
+            "len(fill(0, 1))" => "0"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't pop from an empty array")]
+    fn pop_empty() {
+        eval_str("pop([])");
+    }
+
+    #[test]
+    #[should_panic(expected = "remove got index 3 of an array with length 3")]
+    fn remove_out_of_bounds() {
+        eval_str("remove([1, 2, 3], 3)");
+    }
+
+    #[test]
+    #[should_panic(expected = "range's step can't be 0")]
+    fn zero_step() {
+        eval_str("range(0, 5, 0)");
+    }
+
+    #[test]
+    fn sort_by_comparator() {
+        assert_eq!(
+            run("fn by_float(a, b) { a - b + 0.0 }\njoin(sort([2, 1, 3], by_float), \",\")"),
+            "1,2,3"
+        );
+        assert_eq!(
+            run("fn longest(a, b) { len(b) - len(a) }\njoin(sort([\"a\", \"ccc\", \"bb\"], longest), \",\")"),
+            "ccc,bb,a"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "sort's comparator returned NaN for 1 and 2")]
+    fn sort_comparator_nan() {
+        run("fn nan(a, b) { 0.0 / 0.0 }\nsort([2, 1], nan)");
+    }
+
+    #[test]
+    #[should_panic(expected = "sort's comparator has to return a number, got true")]
+    fn sort_comparator_not_a_number() {
+        run("fn less(a, b) { a < b }\nsort([2, 1], less)");
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't declare a function named concat, it's a builtin")]
+    fn function_named_like_a_builtin() {
+        run("fn concat(a, b) { a }\nconcat([1], [2])");
+    }
+
+    #[test]
+    fn variable_named_like_a_builtin() {
+        // only calls go to the builtin, so other values can use the name
+        assert_eq!(run("let sum = 0\nfor x in [1, 2] { sum += x }\nsum"), "3");
+    }
+}
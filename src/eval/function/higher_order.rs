@@ -0,0 +1,274 @@
+use std::cell::RefCell;
+use std::convert::TryInto;
+use std::rc::Rc;
+
+use crate::eval::iterator::{borrow_mut, Iter};
+use crate::parser::S;
+use crate::Atom;
+use crate::State;
+
+use super::call_value;
+
+// Builtins which take a Slang function and call it on each element. They
+// work on arrays, ranges and iterators, and take one element at a time, so
+// nothing is collected up front and `any`, `all` and `zip` can stop early.
+// `zip` and `enumerate` give back an iterator when they're given one, so
+// they work on generators which never end.
+
+fn items(caller: &str, a: &Atom) -> Rc<RefCell<Iter>> {
+    match a {
+        Atom::Array(_) | Atom::Range(..) | Atom::Iter(_) => Iter::shared(a),
+        _ => panic!("{} expects an array, range or iterator, got {}", caller, a),
+    }
+}
+
+fn advance(it: &RefCell<Iter>, state: &mut State) -> Option<Atom> {
+    borrow_mut(it).next(state)
+}
+
+fn array(elems: impl Iterator<Item = Atom>) -> Atom {
+    Atom::Array(elems.map(S::Atom).collect())
+}
+
+fn tuple(elems: Vec<Atom>) -> Atom {
+    Atom::Tuple(elems.into_iter().map(S::Atom).collect())
+}
+
+fn predicate(caller: &str, f: &Atom, item: Atom, state: &mut State) -> bool {
+    match call_value(caller, f, vec![item], state) {
+        Atom::Bool(b) => b,
+        res => panic!("{}'s function has to return a bool, got {}", caller, res),
+    }
+}
+
+pub fn map(args: &[Atom], state: &mut State) -> Atom {
+    match args {
+        [a, f] => {
+            let it = items("map", a);
+            let mut mapped = Vec::new();
+            while let Some(item) = advance(&it, state) {
+                mapped.push(call_value("map", f, vec![item], state));
+            }
+            array(mapped.into_iter())
+        }
+        _ => panic!("map expects an array and a function, got {:?}", args),
+    }
+}
+
+pub fn filter(args: &[Atom], state: &mut State) -> Atom {
+    match args {
+        [a, f] => {
+            let it = items("filter", a);
+            let mut kept = Vec::new();
+            while let Some(item) = advance(&it, state) {
+                if predicate("filter", f, item.clone(), state) {
+                    kept.push(item);
+                }
+            }
+            array(kept.into_iter())
+        }
+        _ => panic!("filter expects an array and a function, got {:?}", args),
+    }
+}
+
+/// Folds from the left, calling `f(acc, item)`.
+pub fn reduce(args: &[Atom], state: &mut State) -> Atom {
+    match args {
+        [a, f, init] => {
+            let it = items("reduce", a);
+            let mut acc = init.clone();
+            while let Some(item) = advance(&it, state) {
+                acc = call_value("reduce", f, vec![acc, item], state);
+            }
+            acc
+        }
+        _ => panic!(
+            "reduce expects an array, a function and an initial value, got {:?}",
+            args
+        ),
+    }
+}
+
+// Whether any element passes, or with `until` set to false, whether every
+// one does. Stops at the first element that decides it. Without a function,
+// the elements have to be bools themselves.
+fn any_until(caller: &str, args: &[Atom], until: bool, state: &mut State) -> Atom {
+    let (it, f) = match args {
+        [a] => (items(caller, a), None),
+        [a, f] => (items(caller, a), Some(f)),
+        _ => panic!("{} expects an array and a function, got {:?}", caller, args),
+    };
+
+    while let Some(item) = advance(&it, state) {
+        let passed = match f {
+            Some(f) => predicate(caller, f, item, state),
+            None => item == Atom::Bool(true),
+        };
+        if passed == until {
+            return Atom::Bool(until);
+        }
+    }
+    Atom::Bool(!until)
+}
+
+pub fn any(args: &[Atom], state: &mut State) -> Atom {
+    any_until("any", args, true, state)
+}
+
+pub fn all(args: &[Atom], state: &mut State) -> Atom {
+    any_until("all", args, false, state)
+}
+
+/// Pairs up elements as tuples, stopping at the shorter one without taking
+/// anything more from the other.
+pub fn zip(args: &[Atom], state: &mut State) -> Atom {
+    match args {
+        [a, b] if matches!(a, Atom::Iter(_)) || matches!(b, Atom::Iter(_)) => {
+            Iter::zip(items("zip", a), items("zip", b))
+        }
+        [a, b] => {
+            let (a, b) = (items("zip", a), items("zip", b));
+            let mut pairs = Vec::new();
+            while borrow_mut(&a).has_next(state) && borrow_mut(&b).has_next(state) {
+                let x = advance(&a, state).unwrap();
+                let y = advance(&b, state).unwrap();
+                pairs.push(tuple(vec![x, y]));
+            }
+            array(pairs.into_iter())
+        }
+        _ => panic!("zip expects two arrays, got {:?}", args),
+    }
+}
+
+pub fn enumerate(args: &[Atom], state: &mut State) -> Atom {
+    match args {
+        [Atom::Iter(it)] => Iter::enumerate(Rc::clone(it)),
+        [a] => {
+            let it = items("enumerate", a);
+            let mut pairs = Vec::new();
+            while let Some(item) = advance(&it, state) {
+                let i = Atom::Int(pairs.len().try_into().unwrap());
+                pairs.push(tuple(vec![i, item]));
+            }
+            array(pairs.into_iter())
+        }
+        _ => panic!("enumerate expects an array, got {:?}", args),
+    }
+}
+
+pub fn sum(args: &[Atom], state: &mut State) -> Atom {
+    match args {
+        [a] => {
+            let it = items("sum", a);
+            let mut total = Atom::Int(0);
+            while let Some(n) = advance(&it, state) {
+                match n.as_float() {
+                    Some(_) => total = total + n,
+                    None => panic!("sum expects numbers, got {}", n),
+                }
+            }
+            total
+        }
+        _ => panic!("sum expects an array, got {:?}", args),
+    }
+}
+
+#[cfg(test)]
+mod higher_order_tests {
+    use crate::eval::eval_expr;
+    use crate::parser::{parse_block, parse_expr, Lexer};
+    use crate::{scan_tokens, State};
+
+    const FUNCTIONS: &str = "
+fn double(x) { x * 2 }
+fn is_even(x) { x % 2 == 0 }
+fn add(a, b) { a + b }
+fn nothing(x) { let y = x }
+";
+
+    fn eval_str(input: &str) -> String {
+        let mut state = State::default();
+        parse_block(&mut Lexer::new(scan_tokens(FUNCTIONS).unwrap())).execute_unscoped(&mut state);
+        let expr = parse_expr(&mut Lexer::new(scan_tokens(input).unwrap()));
+        eval_expr(&expr, &mut state).to_string()
+    }
+
+    macro_rules! test_builtins {
+        ( $( $input:expr => $expected:expr ),* ) => {
+            $(
+                assert_eq!(eval_str($input), $expected, "{}", $input);
+            )*
+        }
+    }
+
+    #[test]
+    fn builtins() {
+        test_builtins!(
+            "join(map([1, 2, 3], double), \",\")" => "2,4,6",
+            "join(map(0..3, double), \",\")" => "0,2,4",
+            "join(filter(1..7, is_even), \",\")" => "2,4,6",
+            "reduce([1, 2, 3], add, 10)" => "16",
+            "reduce([], add, \"empty\")" => "empty",
+            "any([1, 3, 4], is_even)" => "true",
+            "all([2, 3], is_even)" => "false",
+            "all([], is_even)" => "true",
+            "any([false, true])" => "true",
+            "zip([1, 2, 3], [\"a\", \"b\"])[1][1]" => "b",
+            "len(zip([1, 2, 3], [\"a\", \"b\"]))" => "2",
+            "enumerate([\"a\", \"b\"])[1][0]" => "1",
+            "sum([1, 2, 3])" => "6",
+            "sum([1, 2.5])" => "3.5",
+            "sum([])" => "0",
+            "map([1], nothing)[0]" => "nil"
+        );
+    }
+
+    #[test]
+    fn infinite_generators() {
+        let naturals = "fn naturals() { let n = 0\nwhile (true) { yield n\nn += 1 } }\n";
+        let cases = [
+            ("any(naturals(), is_even)", "true"),
+            ("all(naturals(), is_even)", "false"),
+            ("join(collect(zip(naturals(), [\"a\", \"b\"])), \",\")", "(0, a),(1, b)"),
+            ("next(zip([\"a\"], naturals()))[1]", "0"),
+            ("let e = enumerate(naturals())\nnext(e)\nnext(e)", "(1, 1)"),
+            ("let s = 0\nfor (i, n) in enumerate(naturals()) { if (i == 4) { break }\ns += n }\ns", "6"),
+            // zip doesn't take an item it can't pair up
+            ("let g = naturals()\nlet z = zip(g, [5])\ncollect(z)\nnext(g)", "1"),
+        ];
+        for (input, expected) in cases.iter() {
+            let mut state = State::default();
+            let mut block = parse_block(&mut Lexer::new(
+                scan_tokens(&format!("{}{}{}", FUNCTIONS, naturals, input)).unwrap(),
+            ));
+            assert_eq!(
+                block.execute_unscoped(&mut state).to_string(),
+                *expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn ranges_are_not_collected() {
+        test_builtins!(
+            "any(0..10000000000, is_even)" => "true",
+            "all(1..10000000000, is_even)" => "false",
+            "len(zip(0..10000000000, [\"a\", \"b\"]))" => "2",
+            "zip([\"a\"], 5..10000000000)[0][1]" => "5"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "filter's function has to return a bool, got 2")]
+    fn not_a_predicate() {
+        eval_str("filter([1], double)");
+    }
+
+    #[test]
+    #[should_panic(expected = "map's function takes 2 arguments, but is called with 1")]
+    fn wrong_arity() {
+        eval_str("map([1], add)");
+    }
+}
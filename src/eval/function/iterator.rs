@@ -0,0 +1,45 @@
+use crate::eval::iterator::{borrow_mut, Iter};
+use crate::parser::S;
+use crate::Atom;
+use crate::State;
+
+// The iterator protocol which `for ... in` loops use: `iter(x)` makes an
+// iterator, `has_next(it)` checks whether there's another item and
+// `next(it)` takes it.
+
+pub fn iter(args: &[Atom], _state: &mut State) -> Atom {
+    match args {
+        [a] => Iter::of(a),
+        _ => panic!("iter expects one argument, got {:?}", args),
+    }
+}
+
+pub fn next(args: &[Atom], state: &mut State) -> Atom {
+    match args {
+        [Atom::Iter(it)] => match borrow_mut(it).next(state) {
+            Some(item) => item,
+            None => panic!("next called on a finished iterator"),
+        },
+        _ => panic!("next expects an iterator, got {:?}", args),
+    }
+}
+
+pub fn has_next(args: &[Atom], state: &mut State) -> Atom {
+    match args {
+        [Atom::Iter(it)] => Atom::Bool(borrow_mut(it).has_next(state)),
+        _ => panic!("has_next expects an iterator, got {:?}", args),
+    }
+}
+
+/// Runs an iterator to the end, collecting its items into an array.
+pub fn collect(args: &[Atom], state: &mut State) -> Atom {
+    match args {
+        [a] => Atom::Array(items(a, state).into_iter().map(S::Atom).collect()),
+        _ => panic!("collect expects one argument, got {:?}", args),
+    }
+}
+
+fn items(a: &Atom, state: &mut State) -> Vec<Atom> {
+    let it = Iter::shared(a);
+    std::iter::from_fn(|| borrow_mut(&it).next(state)).collect()
+}
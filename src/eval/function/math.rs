@@ -0,0 +1,237 @@
+use std::cmp::Ordering;
+
+use crate::Atom;
+
+// Ints and Floats mix like they do in arithmetic: functions that only make
+// sense on Floats take Ints too, and the rest keep Ints as Ints.
+
+const CONSTANTS: [(&str, f64); 2] = [("PI", std::f64::consts::PI), ("E", std::f64::consts::E)];
+
+/// The constants every program starts out with.
+pub fn constants() -> Vec<(&'static str, Atom)> {
+    CONSTANTS
+        .iter()
+        .map(|&(name, val)| (name, Atom::Float(val)))
+        .collect()
+}
+
+/// Constants are read-only, they can't be assigned, only shadowed by a
+/// local variable.
+pub fn is_constant(name: &str) -> bool {
+    CONSTANTS.iter().any(|&(constant, _)| constant == name)
+}
+
+fn number(name: &str, a: &Atom) -> f64 {
+    match a.as_float() {
+        Some(n) => n,
+        None => panic!("{} expects numbers, got {}", name, a),
+    }
+}
+
+macro_rules! float_fns {
+    ( $( $name:ident => $f:expr ),* ) => {
+        $(
+            pub fn $name(args: &[Atom]) -> Atom {
+                match args {
+                    [a] => Atom::Float($f(number(stringify!($name), a))),
+                    _ => panic!("{} expects one number, got {:?}", stringify!($name), args),
+                }
+            }
+        )*
+    }
+}
+
+float_fns!(
+    sqrt => f64::sqrt,
+    sin => f64::sin,
+    cos => f64::cos,
+    exp => f64::exp,
+    ln => f64::ln,
+    log10 => f64::log10
+);
+
+pub fn atan2(args: &[Atom]) -> Atom {
+    match args {
+        [y, x] => Atom::Float(number("atan2", y).atan2(number("atan2", x))),
+        _ => panic!("atan2 expects two numbers, got {:?}", args),
+    }
+}
+
+pub fn hypot(args: &[Atom]) -> Atom {
+    match args {
+        [a, b] => Atom::Float(number("hypot", a).hypot(number("hypot", b))),
+        _ => panic!("hypot expects two numbers, got {:?}", args),
+    }
+}
+
+pub fn pow(args: &[Atom]) -> Atom {
+    match args {
+        [a, b] => a.pow(b),
+        _ => panic!("pow expects two numbers, got {:?}", args),
+    }
+}
+
+// Floats get rounded to Ints, Ints are already there
+fn to_int(name: &str, args: &[Atom], f: fn(f64) -> f64) -> Atom {
+    match args {
+        [Atom::Float(n)] => Atom::Int(f(*n) as isize),
+        [n] if n.type_name() == "int" => n.clone(),
+        _ => panic!("{} expects one number, got {:?}", name, args),
+    }
+}
+
+pub fn round(args: &[Atom]) -> Atom {
+    to_int("round", args, f64::round)
+}
+
+pub fn floor(args: &[Atom]) -> Atom {
+    to_int("floor", args, f64::floor)
+}
+
+pub fn ceil(args: &[Atom]) -> Atom {
+    to_int("ceil", args, f64::ceil)
+}
+
+pub fn trunc(args: &[Atom]) -> Atom {
+    to_int("trunc", args, f64::trunc)
+}
+
+pub fn abs(args: &[Atom]) -> Atom {
+    match args {
+        [a] => {
+            number("abs", a);
+            if *a < Atom::Int(0) {
+                a.clone().negate()
+            } else {
+                a.clone()
+            }
+        }
+        _ => panic!("abs expects one number, got {:?}", args),
+    }
+}
+
+// the first of the smallest or largest arguments, keeping its type
+fn extreme(name: &str, args: &[Atom], keep: Ordering) -> Atom {
+    let (first, rest) = match args.split_first() {
+        Some(split) => split,
+        None => panic!("{} expects at least one argument", name),
+    };
+
+    let best = rest.iter().fold(first, |best, a| {
+        if a.partial_cmp(best) == Some(keep) {
+            a
+        } else {
+            best
+        }
+    });
+    best.clone()
+}
+
+pub fn min(args: &[Atom]) -> Atom {
+    extreme("min", args, Ordering::Less)
+}
+
+pub fn max(args: &[Atom]) -> Atom {
+    extreme("max", args, Ordering::Greater)
+}
+
+pub fn clamp(args: &[Atom]) -> Atom {
+    match args {
+        [_, lo, hi] if lo > hi => panic!("Can't clamp between {} and {}", lo, hi),
+        [x, lo, _] if x < lo => lo.clone(),
+        [x, _, hi] if x > hi => hi.clone(),
+        [x, _, _] => x.clone(),
+        _ => panic!("clamp expects a value and two bounds, got {:?}", args),
+    }
+}
+
+#[cfg(test)]
+mod math_tests {
+    use crate::eval::eval_expr;
+    use crate::parser::{parse_block, parse_expr, Lexer};
+    use crate::{scan_tokens, State};
+
+    fn eval_str(input: &str) -> String {
+        let expr = parse_expr(&mut Lexer::new(scan_tokens(input).unwrap()));
+        eval_expr(&expr, &mut State::default()).to_string()
+    }
+
+    macro_rules! test_builtins {
+        ( $( $input:expr => $expected:expr ),* ) => {
+            $(
+                assert_eq!(eval_str($input), $expected, "{}", $input);
+            )*
+        }
+    }
+
+    #[test]
+    fn builtins() {
+        test_builtins!(
+            "sqrt(16)" => "4",
+            "sqrt(2.25)" => "1.5",
+            "pow(2, 10)" => "1024",
+            "pow(4, 0.5)" => "2",
+            "abs(-3)" => "3",
+            "abs(-2.5)" => "2.5",
+            "abs(7)" => "7",
+            "min(3, 1.5, 2)" => "1.5",
+            "max(3, 1.5, 2)" => "3",
+            "min(4)" => "4",
+            "max(\"b\", \"a\")" => "b",
+            "ceil(1.2)" => "2",
+            "ceil(-1.2)" => "-1",
+            "trunc(-1.8)" => "-1",
+            "trunc(5)" => "5",
+            "round(2.5)" => "3",
+            "floor(-2.5)" => "-3",
+            "sin(0)" => "0",
+            "cos(PI)" => "-1",
+            "atan2(1, 1) * 4 == PI" => "true",
+            "exp(0)" => "1",
+            "ln(E)" => "1",
+            "log10(1000)" => "3",
+            "clamp(5, 0, 3)" => "3",
+            "clamp(-1, 0, 3)" => "0",
+            "clamp(1.5, 0, 3)" => "1.5",
+            "hypot(3, 4)" => "5"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't clamp between 3 and 0")]
+    fn clamp_bounds() {
+        eval_str("clamp(1, 3, 0)");
+    }
+
+    #[test]
+    #[should_panic(expected = "sqrt expects numbers, got abc")]
+    fn not_a_number() {
+        eval_str("sqrt(\"abc\")");
+    }
+
+    fn run(input: &str) -> String {
+        parse_block(&mut Lexer::new(scan_tokens(input).unwrap()))
+            .execute_unscoped(&mut State::default())
+            .to_string()
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't assign to PI, it's a constant")]
+    fn assign_constant() {
+        run("PI = 3.0");
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't declare E, it's a constant")]
+    fn redeclare_constant() {
+        run("let E = 2");
+    }
+
+    #[test]
+    fn shadow_constant() {
+        assert_eq!(run("fn f(E) { E * 2 }\nf(3)"), "6");
+        assert_eq!(run("fn f() { let PI = 3\nPI += 1\nPI }\nf()"), "4");
+        // the constant is back once the local goes out of scope
+        assert_eq!(run("fn f(PI) { PI }\nf(1) < PI"), "true");
+    }
+}
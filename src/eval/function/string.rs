@@ -0,0 +1,178 @@
+use std::convert::TryInto;
+
+use crate::eval::error::{raise, RuntimeError};
+use crate::parser::S;
+use crate::Atom;
+
+// Positions and counts are in Unicode scalar values, like indexing strings.
+
+fn str_array(strs: impl Iterator<Item = String>) -> Atom {
+    Atom::Array(strs.map(|s| S::Atom(Atom::Str(s))).collect())
+}
+
+pub fn split(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(_), Atom::Str(sep)] if sep.is_empty() => {
+            panic!("Can't split by an empty string, use chars instead")
+        }
+        [Atom::Str(s), Atom::Str(sep)] => str_array(s.split(sep.as_str()).map(String::from)),
+        _ => panic!("split expects a string and a separator, got {:?}", args),
+    }
+}
+
+pub fn join(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Array(a), Atom::Str(sep)] => {
+            let parts: Vec<String> = a.iter().map(|e| e.to_string()).collect();
+            Atom::Str(parts.join(sep))
+        }
+        _ => panic!("join expects an array and a separator, got {:?}", args),
+    }
+}
+
+pub fn trim(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s)] => Atom::Str(s.trim().to_string()),
+        _ => panic!("trim expects a string, got {:?}", args),
+    }
+}
+
+pub fn upper(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s)] => Atom::Str(s.to_uppercase()),
+        _ => panic!("upper expects a string, got {:?}", args),
+    }
+}
+
+pub fn lower(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s)] => Atom::Str(s.to_lowercase()),
+        _ => panic!("lower expects a string, got {:?}", args),
+    }
+}
+
+pub fn replace(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s), Atom::Str(from), Atom::Str(to)] => Atom::Str(s.replace(from.as_str(), to)),
+        _ => panic!("replace expects three strings, got {:?}", args),
+    }
+}
+
+pub fn starts_with(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s), Atom::Str(prefix)] => Atom::Bool(s.starts_with(prefix.as_str())),
+        _ => panic!("starts_with expects two strings, got {:?}", args),
+    }
+}
+
+pub fn ends_with(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s), Atom::Str(suffix)] => Atom::Bool(s.ends_with(suffix.as_str())),
+        _ => panic!("ends_with expects two strings, got {:?}", args),
+    }
+}
+
+/// The position of the first occurrence of a substring, or -1.
+pub fn find(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s), Atom::Str(sub)] => match s.find(sub.as_str()) {
+            Some(byte) => Atom::Int(s[..byte].chars().count().try_into().unwrap()),
+            None => Atom::Int(-1),
+        },
+        _ => panic!("find expects two strings, got {:?}", args),
+    }
+}
+
+pub fn chars(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s)] => str_array(s.chars().map(String::from)),
+        _ => panic!("chars expects a string, got {:?}", args),
+    }
+}
+
+pub fn to_string(args: &[Atom]) -> Atom {
+    match args {
+        [a] => Atom::Str(a.to_string()),
+        _ => panic!("to_string expects one argument, got {:?}", args),
+    }
+}
+
+// surrounding whitespace is ignored, so lines read from somewhere parse
+pub fn parse_int(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s)] => match s.trim().parse() {
+            Ok(n) => Atom::Int(n),
+            Err(_) => raise(RuntimeError::InvalidNumber {
+                input: s.clone(),
+                expected: "an int",
+            }),
+        },
+        _ => panic!("parse_int expects a string, got {:?}", args),
+    }
+}
+
+pub fn parse_float(args: &[Atom]) -> Atom {
+    match args {
+        [Atom::Str(s)] => match s.trim().parse() {
+            Ok(n) => Atom::Float(n),
+            Err(_) => raise(RuntimeError::InvalidNumber {
+                input: s.clone(),
+                expected: "a float",
+            }),
+        },
+        _ => panic!("parse_float expects a string, got {:?}", args),
+    }
+}
+
+#[cfg(test)]
+mod string_tests {
+    use crate::eval::{error, eval_expr};
+    use crate::parser::{parse_expr, Lexer};
+    use crate::{scan_tokens, State};
+
+    fn eval_str(input: &str) -> Result<String, error::RuntimeError> {
+        let expr = parse_expr(&mut Lexer::new(scan_tokens(input).unwrap()));
+        error::catch(&mut State::default(), |state| {
+            eval_expr(&expr, state).to_string()
+        })
+    }
+
+    macro_rules! test_builtins {
+        ( $( $input:expr => $expected:expr ),* ) => {
+            $(
+                assert_eq!(eval_str($input).unwrap(), $expected, "{}", $input);
+            )*
+        }
+    }
+
+    #[test]
+    fn builtins() {
+        test_builtins!(
+            r#"len(split("a,b,,c", ","))"# => "4",
+            r#"split("a, b", ", ")[1]"# => "b",
+            r#"join(split("a b c", " "), "-")"# => "a-b-c",
+            r#"join([1, 2.5, true], ", ")"# => "1, 2.5, true",
+            r#"join([], ",")"# => "",
+            r#"trim("  hi  ")"# => "hi",
+            r#"upper("straße")"# => "STRASSE",
+            r#"lower("ÉCOLE")"# => "école",
+            r#"replace("a-b-c", "-", "+")"# => "a+b+c",
+            r#"starts_with("slang", "sl")"# => "true",
+            r#"ends_with("slang", "sl")"# => "false",
+            r#"find("héllo", "l")"# => "2",
+            r#"find("héllo", "x")"# => "-1",
+            r#"join(chars("hé"), "|")"# => "h|é",
+            r#"to_string(12) + to_string(1.5)"# => "121.5",
+            r#"parse_int(" 42 ") + 1"# => "43",
+            r#"parse_float("2.5") * 2"# => "5"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |s| eval_str(s).unwrap_err().to_string();
+        assert_eq!(err(r#"parse_int("12x")"#), r#"Can't parse "12x" as an int"#);
+        assert_eq!(err(r#"parse_int("1.5")"#), r#"Can't parse "1.5" as an int"#);
+        assert_eq!(err(r#"parse_float("")"#), r#"Can't parse "" as a float"#);
+    }
+}
@@ -0,0 +1,340 @@
+use std::cell::{RefCell, RefMut};
+use std::rc::Rc;
+
+use crate::block::Block;
+use crate::eval::atom::{Atom, FunctionData};
+use crate::eval::eval_expr;
+use crate::parser::S;
+use crate::statement::{If, Scope, State, Stmt, While};
+
+/// A lazy sequence of values, what `for ... in` loops over. Iterators are
+/// shared rather than copied, so advancing one through any variable
+/// advances it everywhere.
+#[derive(Debug)]
+pub struct Iter {
+    source: Source,
+    // the next item once `has_next` has looked ahead for it, `Some(None)`
+    // when there isn't one
+    peeked: Option<Option<Atom>>,
+}
+
+#[derive(Debug)]
+enum Source {
+    Array(std::vec::IntoIter<S>),
+    // the string and the byte offset of the next character
+    Chars(String, usize),
+    Range(isize, isize),
+    Generator(Generator),
+    // the index of the next item, and the iterator it comes from
+    Enumerate(isize, Rc<RefCell<Iter>>),
+    Zip(Rc<RefCell<Iter>>, Rc<RefCell<Iter>>),
+}
+
+impl Iter {
+    /// Makes an iterator over an array, tuple, string or range. Iterators are
+    /// returned as they are.
+    pub fn of(a: &Atom) -> Atom {
+        let source = match a {
+            Atom::Iter(_) => return a.clone(),
+            Atom::Array(elems) | Atom::Tuple(elems) => Source::Array(elems.clone().into_iter()),
+            Atom::Str(s) => Source::Chars(s.clone(), 0),
+            Atom::Range(start, end) => Source::Range(*start, *end),
+            _ => panic!("Can't iterate over {}", a),
+        };
+        Iter::from_source(source)
+    }
+
+    /// Like `of`, but gives the shared iterator itself, to be advanced with
+    /// `borrow_mut`.
+    pub fn shared(a: &Atom) -> Rc<RefCell<Iter>> {
+        match Iter::of(a) {
+            Atom::Iter(it) => it,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Calling a generator function doesn't run it, it makes an iterator
+    /// which runs the body up to the next `yield` whenever it's advanced.
+    pub fn generator(name: &str, fn_data: FunctionData, args: Vec<Atom>) -> Atom {
+        let mut scope = Scope::default();
+        for (arg_name, arg_val) in fn_data.arg_names.into_iter().zip(args) {
+            scope.vars.insert(arg_name, arg_val);
+        }
+
+        Iter::from_source(Source::Generator(Generator {
+            name: name.to_string(),
+            scopes: vec![scope],
+            frames: vec![Frame::Block {
+                block: fn_data.fn_block.as_ref().clone(),
+                pc: 0,
+                scoped: false,
+            }],
+        }))
+    }
+
+    /// Pairs each item of an iterator with its index, as it's advanced.
+    pub fn enumerate(inner: Rc<RefCell<Iter>>) -> Atom {
+        Iter::from_source(Source::Enumerate(0, inner))
+    }
+
+    /// Pairs up the items of two iterators, ending with the shorter one
+    /// without taking anything more from the other.
+    pub fn zip(a: Rc<RefCell<Iter>>, b: Rc<RefCell<Iter>>) -> Atom {
+        Iter::from_source(Source::Zip(a, b))
+    }
+
+    fn from_source(source: Source) -> Atom {
+        Atom::Iter(Rc::new(RefCell::new(Iter {
+            source,
+            peeked: None,
+        })))
+    }
+
+    pub fn next(&mut self, state: &mut State) -> Option<Atom> {
+        if let Some(peeked) = self.peeked.take() {
+            return peeked;
+        }
+
+        match &mut self.source {
+            Source::Array(items) => items.next().map(|item| match item {
+                S::Atom(a) => a,
+                _ => unreachable!(),
+            }),
+            Source::Chars(s, pos) => {
+                let c = s[*pos..].chars().next()?;
+                *pos += c.len_utf8();
+                Some(Atom::Str(c.to_string()))
+            }
+            Source::Range(start, end) => {
+                if start < end {
+                    *start += 1;
+                    Some(Atom::Int(*start - 1))
+                } else {
+                    None
+                }
+            }
+            Source::Generator(generator) => generator.resume(state),
+            Source::Enumerate(i, inner) => {
+                let item = borrow_mut(inner).next(state)?;
+                *i += 1;
+                Some(pair(Atom::Int(*i - 1), item))
+            }
+            Source::Zip(a, b) => {
+                if !borrow_mut(a).has_next(state) || !borrow_mut(b).has_next(state) {
+                    return None;
+                }
+                let x = borrow_mut(a).next(state)?;
+                let y = borrow_mut(b).next(state)?;
+                Some(pair(x, y))
+            }
+        }
+    }
+
+    pub fn has_next(&mut self, state: &mut State) -> bool {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.next(state));
+        }
+        matches!(self.peeked, Some(Some(_)))
+    }
+}
+
+fn pair(a: Atom, b: Atom) -> Atom {
+    Atom::Tuple(vec![S::Atom(a), S::Atom(b)])
+}
+
+/// Borrows a shared iterator to advance it, which a generator can't do to
+/// itself while it's running.
+pub fn borrow_mut(iter: &RefCell<Iter>) -> RefMut<'_, Iter> {
+    match iter.try_borrow_mut() {
+        Ok(iter) => iter,
+        Err(_) => panic!("A generator can't advance itself"),
+    }
+}
+
+/// The suspended body of a generator function. The tree walker keeps its
+/// place in the Rust stack, so generators have their own interpreter which
+/// keeps it in `frames` instead and can stop at a `yield`.
+#[derive(Debug)]
+struct Generator {
+    name: String,
+    // the generator's own scopes, only on the State's scope stack while it
+    // runs
+    scopes: Vec<Scope>,
+    frames: Vec<Frame>,
+}
+
+#[derive(Debug)]
+enum Frame {
+    // the index of the next statement to run, blocks with a scope pop it
+    // when they finish
+    Block {
+        block: Block,
+        pc: usize,
+        scoped: bool,
+    },
+    Loop(While),
+}
+
+impl Generator {
+    fn resume(&mut self, state: &mut State) -> Option<Atom> {
+        state.enter_call(&self.name);
+        let base = state.scopes.len();
+        state.scopes.append(&mut self.scopes);
+
+        let res = self.run(state);
+
+        self.scopes = state.scopes.split_off(base);
+        state.exit_call();
+        res
+    }
+
+    /// Runs statements until one yields or the body is done.
+    fn run(&mut self, state: &mut State) -> Option<Atom> {
+        while let Some(frame) = self.frames.last_mut() {
+            let stmt = match frame {
+                Frame::Block { block, pc, scoped } => match block.statements.get(*pc) {
+                    Some(stmt) => {
+                        *pc += 1;
+                        stmt.clone()
+                    }
+                    None => {
+                        if *scoped {
+                            state.scopes.pop();
+                        }
+                        self.frames.pop();
+                        continue;
+                    }
+                },
+                Frame::Loop(While { cond, loop_block }) => {
+                    if eval_expr(cond, state) == Atom::Bool(true) {
+                        let block = loop_block.clone();
+                        self.enter(block, state);
+                    } else {
+                        self.frames.pop();
+                    }
+                    continue;
+                }
+            };
+
+            match stmt {
+                Stmt::Yield(expr) => return Some(eval_expr(&expr, state)),
+                Stmt::IfStmt(If {
+                    cond,
+                    then_block,
+                    else_block,
+                }) => {
+                    if eval_expr(&cond, state) == Atom::Bool(true) {
+                        self.enter(then_block, state);
+                    } else {
+                        self.enter(else_block, state);
+                    }
+                }
+                Stmt::WhileStmt(while_data) => self.frames.push(Frame::Loop(while_data)),
+                // the arm's scope is already pushed
+                Stmt::MatchStmt(match_data) => self.frames.push(Frame::Block {
+                    block: match_data.enter_arm(state).clone(),
+                    pc: 0,
+                    scoped: true,
+                }),
+                Stmt::Block(block) => self.enter(block, state),
+                Stmt::Break => self.break_loop(state),
+                stmt => {
+                    stmt.execute(state);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn enter(&mut self, block: Block, state: &mut State) {
+        state.scopes.push(Scope::default());
+        self.frames.push(Frame::Block {
+            block,
+            pc: 0,
+            scoped: true,
+        });
+    }
+
+    // outside of a loop, this ends the generator
+    fn break_loop(&mut self, state: &mut State) {
+        while let Some(frame) = self.frames.pop() {
+            match frame {
+                Frame::Loop(_) => return,
+                Frame::Block { scoped: true, .. } => {
+                    state.scopes.pop();
+                }
+                Frame::Block { .. } => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod iterator_tests {
+    use crate::parser::{parse_block, Lexer};
+    use crate::{scan_tokens, State};
+
+    fn run(input: &str) -> String {
+        let mut state = State::default();
+        parse_block(&mut Lexer::new(scan_tokens(input).unwrap()))
+            .execute_unscoped(&mut state)
+            .to_string()
+    }
+
+    const COUNT: &str = "
+fn count(from, to) {
+    while (from < to) {
+        yield from
+        from += 1
+    }
+}
+";
+
+    #[test]
+    fn generators() {
+        // each call gets its own arguments and position
+        let input = "let a = count(0, 3)\nlet b = count(10, 13)\nnext(a)\n\"{next(a)} {next(b)}\"";
+        assert_eq!(run(&format!("{}{}", COUNT, input)), "1 10");
+
+        // calls in tail position still just make the iterator
+        let input = "fn wrapped() { count(5, 7) }\nsum(wrapped())";
+        assert_eq!(run(&format!("{}{}", COUNT, input)), "11");
+
+        // a break outside of a loop ends the generator
+        let input =
+            "fn once() { yield 1\nif (true) { break }\nyield 2 }\njoin(collect(once()), \",\")";
+        assert_eq!(run(input), "1");
+
+        // has_next doesn't skip anything
+        let input =
+            "let c = count(0, 2)\nhas_next(c)\nhas_next(c)\n\"{next(c)} {next(c)} {has_next(c)}\"";
+        assert_eq!(run(&format!("{}{}", COUNT, input)), "0 1 false");
+    }
+
+    #[test]
+    fn iterators_are_shared() {
+        assert_eq!(
+            run("let a = iter(0..5)\nlet b = a\nnext(a)\nnext(b)\nnext(a)"),
+            "2"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "next called on a finished iterator")]
+    fn finished() {
+        run("let a = iter(\"x\")\nnext(a)\nnext(a)");
+    }
+
+    #[test]
+    #[should_panic(expected = "A generator can't advance itself")]
+    fn advance_itself() {
+        run("fn gen() { yield next(g) }\nlet g = gen()\nnext(g)");
+    }
+
+    #[test]
+    #[should_panic(expected = "yield can only be used in a function")]
+    fn top_level_yield() {
+        run("yield 1");
+    }
+}
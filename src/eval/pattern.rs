@@ -0,0 +1,291 @@
+use std::fmt;
+
+use crate::eval::atom::Atom;
+use crate::parser::S;
+
+/// The left hand side of a destructuring `let`, like `(x, y)` or
+/// `[first, ..rest]`, or what a `match` arm matches against. Patterns can
+/// be nested.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    // `_`, matches anything without binding it
+    Wildcard,
+    Name(String),
+    // a number, string, bool or nil, matches values equal to it
+    Literal(Atom),
+    Tuple(Vec<Pattern>),
+    // with a rest pattern after `..`, the array can be longer and the rest
+    // of it is matched against that as an array
+    Array {
+        items: Vec<Pattern>,
+        rest: Option<Box<Pattern>>,
+    },
+}
+
+impl Pattern {
+    /// Matches a value against the pattern, adding the variables it binds
+    /// to `bindings`. Tuple patterns only match tuples and array patterns
+    /// only match arrays.
+    pub fn matches(&self, value: &Atom, bindings: &mut Vec<(String, Atom)>) -> bool {
+        match (self, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Name(name), _) => {
+                bindings.push((name.clone(), value.clone()));
+                true
+            }
+            (Pattern::Literal(lit), _) => lit == value,
+            (Pattern::Tuple(items), Atom::Tuple(elems)) => {
+                items.len() == elems.len() && matches_all(items, elems, bindings)
+            }
+            (Pattern::Array { items, rest: None }, Atom::Array(elems)) => {
+                items.len() == elems.len() && matches_all(items, elems, bindings)
+            }
+            (
+                Pattern::Array {
+                    items,
+                    rest: Some(rest),
+                },
+                Atom::Array(elems),
+            ) => {
+                items.len() <= elems.len()
+                    && matches_all(items, &elems[..items.len()], bindings)
+                    && rest.matches(&Atom::Array(elems[items.len()..].to_vec()), bindings)
+            }
+            _ => false,
+        }
+    }
+
+    /// The names of the variables the pattern binds.
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) => vec![],
+            Pattern::Name(name) => vec![name],
+            Pattern::Tuple(items) => items.iter().flat_map(Pattern::names).collect(),
+            Pattern::Array { items, rest } => items
+                .iter()
+                .chain(rest.as_deref())
+                .flat_map(Pattern::names)
+                .collect(),
+        }
+    }
+}
+
+fn matches_all(items: &[Pattern], elems: &[S], bindings: &mut Vec<(String, Atom)>) -> bool {
+    items.iter().zip(elems).all(|(item, elem)| match elem {
+        S::Atom(elem) => item.matches(elem, bindings),
+        _ => unreachable!(),
+    })
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |items: &[Pattern]| {
+            items
+                .iter()
+                .map(|item| item.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Name(name) => write!(f, "{}", name),
+            Pattern::Literal(Atom::Str(s)) => write!(f, "{:?}", s),
+            Pattern::Literal(lit) => write!(f, "{}", lit),
+            Pattern::Tuple(items) if items.len() == 1 => write!(f, "({},)", items[0]),
+            Pattern::Tuple(items) => write!(f, "({})", join(items)),
+            Pattern::Array { items, rest: None } => write!(f, "[{}]", join(items)),
+            Pattern::Array {
+                items,
+                rest: Some(rest),
+            } => {
+                let rest = match rest.as_ref() {
+                    Pattern::Wildcard => "..".to_string(),
+                    rest => format!("..{}", rest),
+                };
+                if items.is_empty() {
+                    write!(f, "[{}]", rest)
+                } else {
+                    write!(f, "[{}, {}]", join(items), rest)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod pattern_tests {
+    use crate::eval::error::{catch, RuntimeError};
+    use crate::parser::{parse_block, Lexer};
+    use crate::{scan_tokens, State};
+
+    fn run(input: &str) -> String {
+        let mut state = State::default();
+        parse_block(&mut Lexer::new(scan_tokens(input).unwrap()))
+            .execute_unscoped(&mut state)
+            .to_string()
+    }
+
+    #[test]
+    fn destructuring() {
+        assert_eq!(run("let (a, [b, c]) = (1, [2, 3])\na + b + c"), "6");
+        assert_eq!(run("let [a, ..rest] = [1]\nlen(rest)"), "0");
+        assert_eq!(run("let [a, ..] = [1, 2]\na"), "1");
+        assert_eq!(run("let (x,) = (4,)\nx"), "4");
+        assert_eq!(
+            run("let (a, b) = (1, 2)\nlet (a, b) = (b, a)\n(a, b)"),
+            "(2, 1)"
+        );
+    }
+
+    #[test]
+    fn for_patterns() {
+        // a parenthesised pattern is still a for-in loop
+        assert_eq!(run("let s = 0\nfor (x) in [1, 2] { s += x }\ns"), "3");
+        assert_eq!(
+            run("let s = 0\nfor ((a, b)) in [(1, 2), (3, 4)] { s += a * b }\ns"),
+            "14"
+        );
+        assert_eq!(
+            run("let s = 0\nfor (let i = 0; i < 3; i += 1) { s += i }\ns"),
+            "3"
+        );
+        assert_eq!(run("let s = 0\nfor (;;) { s += 1\nbreak }\ns"), "1");
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't destructure (1, 2, 3) into (a, b)")]
+    fn wrong_length() {
+        run("let (a, b) = (1, 2, 3)");
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't destructure (1, 2) into [a, ..]")]
+    fn tuple_as_array() {
+        run("let [a, ..] = (1, 2)");
+    }
+
+    #[test]
+    #[should_panic(expected = "The rest of an array pattern has to come last")]
+    fn rest_in_the_middle() {
+        run("let [a, ..rest, b] = [1, 2, 3]");
+    }
+
+    #[test]
+    fn match_arms() {
+        let describe = "fn f(x) { match x { 0 | 1 => \"bit\", -2.5 => \"neg\", \"a\" => \"str\", \
+                        (a, a2) if a == a2 => \"same\", (a, _) => \"{a}\", _ => \"other\" } }\n";
+        let results = "join(map([1, -2.5, \"a\", (2, 2), (3, 4), [5]], f), \" \")";
+        assert_eq!(
+            run(&format!("{}{}", describe, results)),
+            "bit neg str same 3 other"
+        );
+
+        // bindings only live as long as their arm
+        assert_eq!(run("let x = 1\nmatch 5 { x => x }\nx"), "1");
+        // matches in tail position don't count towards the call depth
+        assert_eq!(
+            run("fn down(n) { match n { 0 => \"done\", _ => down(n - 1) } }\ndown(20000)"),
+            "done"
+        );
+    }
+
+    #[test]
+    fn no_match() {
+        let block = parse_block(&mut Lexer::new(
+            scan_tokens("match 1 { true => 0, false => 1 }").unwrap(),
+        ));
+        let err = catch(&mut State::default(), |state| {
+            block.clone().execute_unscoped(state)
+        });
+        assert_eq!(
+            err.unwrap_err(),
+            RuntimeError::NoMatch {
+                value: "1".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn exhaustive_without_a_catch_all() {
+        assert_eq!(run("match 1 < 2 { true => 1, false => 0 }"), "1");
+        assert_eq!(
+            run("match (false, 3) { (true, _) => 0, (false, x) => x }"),
+            "3"
+        );
+        assert_eq!(
+            run("match [1, 2] { [] => 0, [x, ..rest] => x + len(rest) }"),
+            "2"
+        );
+        assert_eq!(run("match 4 { nil => 0, n => n }"), "4");
+    }
+
+    #[test]
+    #[should_panic(expected = "The match doesn't cover every value, add a `_` arm")]
+    fn non_exhaustive_numbers() {
+        run("match 1 { 0 => 1, 1 => 2 }");
+    }
+
+    #[test]
+    #[should_panic(expected = "The match doesn't cover every value, add a `_` arm")]
+    fn non_exhaustive_bools() {
+        run("match true { true => 1 }");
+    }
+
+    #[test]
+    #[should_panic(expected = "The match doesn't cover every value, add a `_` arm")]
+    fn non_exhaustive_arrays() {
+        run("match [] { [] => 0, [x] => 1 }");
+    }
+
+    #[test]
+    #[should_panic(expected = "The match doesn't cover every value, add a `_` arm")]
+    fn guarded_arms_dont_count() {
+        run("match true { true => 1, false if 1 > 0 => 0 }");
+    }
+
+    #[test]
+    #[should_panic(expected = "The match doesn't cover every value, add a `_` arm")]
+    fn nil_alone_isnt_exhaustive() {
+        run("let x = nil\nmatch x { nil => 1 }");
+    }
+
+    #[test]
+    fn match_expression() {
+        assert_eq!(run("let y = match 1 { 0 => \"a\", _ => \"b\" }\ny"), "b");
+        assert_eq!(run("1 + match 2 { n => n * 3 }"), "7");
+        assert_eq!(
+            run("len(match [1] { [] => [], xs => concat(xs, [2]) })"),
+            "2"
+        );
+        assert_eq!(run("match match 1 { n => n + 1 } { 2 => 4, _ => 0 }"), "4");
+        assert_eq!(
+            run("let n = 0\nlet y = match n { _ => {\nwhile (true) {\nbreak\n}\n5\n} }\ny"),
+            "5"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "A match expression can't break out of a loop or yield")]
+    fn match_expression_cant_break() {
+        run("while (true) {\nlet y = match 1 { _ => break }\n}");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unreachable match arm 2, n already matches")]
+    fn unreachable_arm() {
+        run("match 1 { 1 => 0, n => n, 2 => 2 }");
+    }
+
+    #[test]
+    #[should_panic(expected = "Every alternative of a match arm has to bind the same variables")]
+    fn alternatives_bind_the_same_names() {
+        run("match (1, 2) { (a, 1) | (1, b) => 0 }");
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't match on Point { ... }, there are no structs yet")]
+    fn struct_pattern() {
+        run("match p { Point { x, y } => x }");
+    }
+}
@@ -1,6 +1,4 @@
-#![feature(or_patterns)]
-#![feature(type_alias_impl_trait)]
-#![feature(bindings_after_at)]
+#![allow(clippy::upper_case_acronyms, clippy::enum_variant_names)]
 
 use crate::eval::atom::Atom;
 use std::error::Error;
@@ -8,6 +6,8 @@ use std::io;
 use std::io::BufRead;
 use std::io::Write;
 
+use itertools::Itertools;
+
 mod scanner;
 use scanner::token::*;
 use scanner::*;
@@ -16,59 +16,112 @@ mod parser;
 use parser::*;
 
 mod eval;
+use eval::error;
 use statement::State;
 
 mod statement;
 
 mod block;
 
-fn run(code: &str, state: &mut State, unscoped: bool) -> Result<Option<Atom>, Box<dyn Error>> {
-    let tokens = scan_tokens(code);
-    if let Some(t) = tokens.iter().find(|t| t.ty == TokenType::Unknown) {
-        Err(format!("Invalid {:?} ({}) on line {}", t.ty, t.lexeme, t.line).into())
+mod bytecode;
+
+mod optimize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    TreeWalk,
+    Vm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    TinyVm,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub backend: Backend,
+    /// Print the script compiled for another target instead of running it
+    pub emit: Option<Emit>,
+    pub opt_level: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            backend: Backend::TreeWalk,
+            emit: None,
+            opt_level: 0,
+        }
+    }
+}
+
+fn run(
+    code: &str,
+    state: &mut State,
+    unscoped: bool,
+    config: &Config,
+) -> Result<Atom, Box<dyn Error>> {
+    let tokens = scan_tokens(code).map_err(|errors| errors.iter().join("\n"))?;
+    // println!(
+    //     "{:?}",
+    //     tokens
+    //         .clone()
+    //         .iter()
+    //         .map(|t| t.ty.clone())
+    //         .collect::<Vec<TokenType>>()
+    // );
+    let mut lexer = Lexer::new(tokens);
+
+    // constant propagation assumes it can see the whole program, which
+    // isn't true for a single REPL input
+    let opt_level = if unscoped {
+        config.opt_level.min(1)
     } else {
-        // println!(
-        //     "{:?}",
-        //     tokens
-        //         .clone()
-        //         .iter()
-        //         .map(|t| t.ty.clone())
-        //         .collect::<Vec<TokenType>>()
-        // );
-        let mut lexer = Lexer::new(tokens);
-
-        let mut main_block = parse_block(&mut lexer);
-
-        let res = if unscoped {
-            main_block.execute_unscoped(state)
-        } else {
-            main_block.execute_unscoped(state)
-        };
-
-        Ok(res)
+        config.opt_level
+    };
+    let mut main_block = optimize::optimize(parse_block(&mut lexer), opt_level);
+
+    if let Some(Emit::TinyVm) = config.emit {
+        print!("{}", bytecode::tinyvm::emit(&main_block)?);
+        return Ok(Atom::Nil);
     }
+
+    let program = match config.backend {
+        Backend::Vm => Some(bytecode::compile(&main_block, !unscoped)?),
+        Backend::TreeWalk => None,
+    };
+
+    let res = error::catch(state, |state| match (program, unscoped) {
+        (Some(program), _) => bytecode::Vm::new(state).run(program),
+        (None, true) => main_block.execute_unscoped(state),
+        (None, false) => main_block.execute(state),
+    })?;
+
+    Ok(res)
 }
 
 fn run_file(
     path: impl AsRef<std::path::Path> + std::fmt::Debug + std::clone::Clone,
     state: &mut State,
-) -> Result<Option<Atom>, Box<dyn Error>> {
+    config: &Config,
+) -> Result<Atom, Box<dyn Error>> {
     let file = std::fs::read_to_string(path)?;
-    let res = run(&file, state, false)?;
-    if let Some(ref a) = res {
-        println!("{}", a);
+    let res = run(&file, state, false, config)?;
+    if res != Atom::Nil {
+        println!("{}", res);
     }
 
     Ok(res)
 }
 
-fn run_prompt(state: &mut State) -> Result<Option<Atom>, Box<dyn Error>> {
+fn run_prompt(state: &mut State, config: &Config) -> Result<Atom, Box<dyn Error>> {
     let stdin = io::stdin();
     let mut stdout = io::stdout();
     let mut buffer = Vec::new();
 
     loop {
-        print!("Slang |>\n");
+        println!("Slang |>");
         stdout.flush()?;
         buffer.clear();
 
@@ -81,46 +134,143 @@ fn run_prompt(state: &mut State) -> Result<Option<Atom>, Box<dyn Error>> {
             break;
         }
 
-        if let Some(a) = run(&buffer_str[..buffer.len() - 2], state, true)? {
-            println!("{}", a);
+        match run(&buffer_str[..buffer.len() - 2], state, true, config) {
+            Ok(Atom::Nil) => {}
+            Ok(a) => println!("{}", a),
+            Err(e) => println!("Error: {}", e),
         }
     }
 
     // TODO: make this return the last expr
-    Ok(None)
+    Ok(Atom::Nil)
+}
+
+// scripts that recurse deeply make the tree walker recurse deeply too, this
+// is enough for the default call depth
+const INTERPRETER_STACK_SIZE: usize = 256 * 1024 * 1024;
+// the stack grows with the call depth, and since variables are looked up
+// through every caller's scope deep recursion is quadratic, so deeper limits
+// would reserve more memory and take longer than is reasonable
+const MAX_CALL_DEPTH: usize = 20_000;
+
+/// The native stack the interpreter needs so that `max_call_depth` nested
+/// calls raise a `StackOverflow` before they overflow it.
+fn stack_size(max_call_depth: usize) -> usize {
+    let per_call = INTERPRETER_STACK_SIZE / statement::DEFAULT_MAX_CALL_DEPTH;
+    INTERPRETER_STACK_SIZE.max(max_call_depth * per_call)
+}
+
+struct Args {
+    config: Config,
+    max_call_depth: usize,
+    paths: Vec<String>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = std::env::args().collect::<Vec<String>>();
+    error::install_panic_hook();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let interpreter = std::thread::Builder::new()
+        .stack_size(stack_size(args.max_call_depth))
+        .spawn(|| interpreter_main(args).map_err(|e| e.to_string()))?;
+
+    match interpreter.join() {
+        Ok(Ok(())) => Ok(()),
+        // printed with Display, there can be several lex errors on separate lines
+        Ok(Err(e)) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        Err(payload) => std::panic::resume_unwind(payload),
+    }
+}
+
+fn parse_args() -> Result<Args, Box<dyn Error>> {
+    let mut config = Config::default();
+    let mut max_call_depth = statement::DEFAULT_MAX_CALL_DEPTH;
+    let mut paths = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--vm" => config.backend = Backend::Vm,
+            "--emit" => match args.next().as_deref() {
+                Some("tinyvm") => config.emit = Some(Emit::TinyVm),
+                target => return Err(format!("Unknown emit target {:?}", target).into()),
+            },
+            "--opt-level" => match args.next().map(|level| level.parse()) {
+                Some(Ok(level)) => config.opt_level = level,
+                _ => return Err("--opt-level takes a number".into()),
+            },
+            "--max-call-depth" => match args.next().map(|depth| depth.parse()) {
+                Some(Ok(depth)) if depth <= MAX_CALL_DEPTH => max_call_depth = depth,
+                Some(Ok(_)) => {
+                    return Err(
+                        format!("--max-call-depth can be at most {}", MAX_CALL_DEPTH).into(),
+                    )
+                }
+                _ => return Err("--max-call-depth takes a number".into()),
+            },
+            flag if flag.starts_with("--") => {
+                return Err(format!("Unknown flag {}", flag).into());
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    Ok(Args {
+        config,
+        max_call_depth,
+        paths,
+    })
+}
+
+fn interpreter_main(args: Args) -> Result<(), Box<dyn Error>> {
+    let Args {
+        config,
+        max_call_depth,
+        paths,
+    } = args;
 
-    let mut top_state = State::default();
+    let mut top_state = State::with_max_call_depth(max_call_depth);
 
     {
         use crate::block::Block;
         use crate::eval::atom::FunctionData;
         use crate::statement::{Declaration, Stmt};
+        use std::rc::Rc;
         top_state.declare(Declaration {
             lhs: "mul".to_string(),
             rhs: S::Atom(Atom::Function(FunctionData {
                 arg_names: vec!["x".to_string(), "y".to_string()],
-                fn_block: Block::new(vec![Stmt::ExprStmt(S::Cons(
+                fn_block: Rc::new(Block::new(vec![Stmt::ExprStmt(S::Cons(
                     Op::Multiply,
                     vec![
                         S::Atom(Atom::Identifier("x".to_string())),
                         S::Atom(Atom::Identifier("y".to_string())),
                     ],
-                ))]),
+                ))])),
+                generator: false,
             })),
             alias: true,
-            plus_or_minus: None,
+            op: None,
         });
     }
 
-    match args.len() {
-        0 | 1 => run_prompt(&mut top_state),
-        2 => run_file(args[1].clone(), &mut top_state),
+    match paths.as_slice() {
+        [] => run_prompt(&mut top_state, &config),
+        [path] => run_file(path, &mut top_state, &config),
         _ => {
-            println!("Usage: rlox [script]");
+            println!(
+                "Usage: slang [--vm] [--emit tinyvm] [--opt-level n] [--max-call-depth n] [script]"
+            );
             Err("bad input".into())
         }
     }?;
@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use crate::block::Block;
+use crate::eval::atom::{Atom, FunctionCall, FunctionData};
+use crate::eval::{binary_op, interpolate, unary_op};
+use crate::parser::{Op, S};
+use crate::statement::{Arm, Declaration, If, Match, Stmt, While};
+
+// --opt-level 1 folds operators applied to literals and removes branches
+// which can never run. --opt-level 2 also treats top level variables which
+// are declared once and never reassigned as literals.
+
+pub fn optimize(block: Block, opt_level: u8) -> Block {
+    match opt_level {
+        0 => block,
+        1 => Folder::default().block(block),
+        _ => propagate_constants(block),
+    }
+}
+
+fn is_literal(a: &Atom) -> bool {
+    matches!(
+        a,
+        Atom::Int(_) | Atom::Float(_) | Atom::Str(_) | Atom::Bool(_) | Atom::Nil
+    )
+}
+
+fn is_numeric(a: &Atom) -> bool {
+    matches!(a, Atom::Int(_) | Atom::Float(_))
+}
+
+// Only operations that evaluate without panicking get folded, so a bad
+// expression in a branch that never runs doesn't become an error.
+fn can_fold_unary(op: Op, a: &Atom) -> bool {
+    match (op, a) {
+        (Op::Minus | Op::Negate, Atom::Int(n)) => n.checked_neg().is_some(),
+        (Op::Minus | Op::Negate, Atom::Float(_) | Atom::Bool(_)) => true,
+        (Op::BitNot, Atom::Int(_)) => true,
+        _ => false,
+    }
+}
+
+fn can_fold_binary(op: Op, a: &Atom, b: &Atom) -> bool {
+    match (op, a, b) {
+        (Op::Equal | Op::NotEqual, a, b) => is_literal(a) && is_literal(b),
+        (Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual, a, b) => {
+            (is_numeric(a) && is_numeric(b)) || matches!((a, b), (Atom::Str(_), Atom::Str(_)))
+        }
+        (Op::And | Op::Or, Atom::Bool(_), Atom::Bool(_)) => true,
+        (Op::Plus, Atom::Int(x), Atom::Int(y)) => x.checked_add(*y).is_some(),
+        (Op::Minus, Atom::Int(x), Atom::Int(y)) => x.checked_sub(*y).is_some(),
+        (Op::Multiply, Atom::Int(x), Atom::Int(y)) => x.checked_mul(*y).is_some(),
+        (Op::Mod, Atom::Int(x), Atom::Int(y)) => x.checked_rem(*y).is_some(),
+        (Op::Mod, Atom::Float(_), Atom::Float(_)) => true,
+        (Op::FloorDivide, Atom::Int(x), Atom::Int(y)) => x.checked_div(*y).is_some(),
+        // dividing by 0 gives a Float
+        (Op::Divide, Atom::Int(x), Atom::Int(y)) => *y == 0 || x.checked_div(*y).is_some(),
+        (Op::Pow, Atom::Int(x), Atom::Int(y)) if *y >= 0 => u32::try_from(*y)
+            .ok()
+            .and_then(|y| x.checked_pow(y))
+            .is_some(),
+        (Op::BitAnd | Op::BitOr | Op::BitXor, Atom::Int(_), Atom::Int(_)) => true,
+        (Op::ShiftLeft, Atom::Int(x), Atom::Int(y)) => u32::try_from(*y)
+            .ok()
+            .and_then(|y| x.checked_shl(y))
+            .is_some_and(|n| n >> y == *x),
+        (Op::ShiftRight, Atom::Int(_), Atom::Int(y)) => (0..isize::BITS as isize).contains(y),
+        (Op::Plus, a, b) => {
+            let addable = |x: &Atom| is_numeric(x) || matches!(x, Atom::Str(_));
+            addable(a) && addable(b)
+        }
+        (Op::Minus | Op::Multiply | Op::Divide | Op::FloorDivide | Op::Pow, a, b) => {
+            is_numeric(a) && is_numeric(b)
+        }
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Folder {
+    consts: BTreeMap<String, Atom>,
+}
+
+impl Folder {
+    fn block(&self, block: Block) -> Block {
+        Block::new(block.statements.into_iter().map(|s| self.stmt(s)).collect())
+    }
+
+    fn stmt(&self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::ExprStmt(expr) => Stmt::ExprStmt(self.expr(expr)),
+            Stmt::PrintStmt(expr) => Stmt::PrintStmt(self.expr(expr)),
+            Stmt::Dec(dec) => Stmt::Dec(Declaration {
+                rhs: self.expr(dec.rhs),
+                ..dec
+            }),
+            Stmt::IfStmt(If {
+                cond,
+                then_block,
+                else_block,
+            }) => match self.expr(cond) {
+                S::Atom(a) if is_literal(&a) => {
+                    if a == Atom::Bool(true) {
+                        Stmt::Block(self.block(then_block))
+                    } else {
+                        Stmt::Block(self.block(else_block))
+                    }
+                }
+                cond => Stmt::IfStmt(If {
+                    cond,
+                    then_block: self.block(then_block),
+                    else_block: self.block(else_block),
+                }),
+            },
+            Stmt::WhileStmt(While { cond, loop_block }) => match self.expr(cond) {
+                S::Atom(a) if is_literal(&a) && a != Atom::Bool(true) => {
+                    Stmt::Block(Block::new(Vec::new()))
+                }
+                cond => Stmt::WhileStmt(While {
+                    cond,
+                    loop_block: self.block(loop_block),
+                }),
+            },
+            Stmt::MatchStmt(match_data) => Stmt::MatchStmt(self.match_arms(match_data)),
+            Stmt::Block(b) => Stmt::Block(self.block(b)),
+            Stmt::Break => Stmt::Break,
+            Stmt::Yield(expr) => Stmt::Yield(self.expr(expr)),
+            Stmt::Destructure(pattern, rhs) => Stmt::Destructure(pattern, self.expr(rhs)),
+        }
+    }
+
+    fn match_arms(&self, Match { value, arms }: Match) -> Match {
+        Match {
+            value: self.expr(value),
+            arms: arms
+                .into_iter()
+                .map(|arm| Arm {
+                    guard: arm.guard.map(|guard| self.expr(guard)),
+                    block: self.block(arm.block),
+                    ..arm
+                })
+                .collect(),
+        }
+    }
+
+    fn expr(&self, expr: S) -> S {
+        match expr {
+            S::Atom(Atom::Identifier(name)) => match self.consts.get(&name) {
+                Some(a) => S::Atom(a.clone()),
+                None => S::Atom(Atom::Identifier(name)),
+            },
+            S::Atom(Atom::Array(elems)) => S::Atom(Atom::Array(
+                elems.into_iter().map(|e| self.expr(e)).collect(),
+            )),
+            S::Atom(Atom::Tuple(elems)) => S::Atom(Atom::Tuple(
+                elems.into_iter().map(|e| self.expr(e)).collect(),
+            )),
+            S::Atom(Atom::FnCall(FunctionCall { name, args })) => {
+                S::Atom(Atom::FnCall(FunctionCall {
+                    name,
+                    args: args.into_iter().map(|a| self.expr(a)).collect(),
+                }))
+            }
+            S::Atom(Atom::Function(FunctionData {
+                arg_names,
+                fn_block,
+                generator,
+            })) => S::Atom(Atom::Function(FunctionData {
+                arg_names,
+                fn_block: Rc::new(self.block(fn_block.as_ref().clone())),
+                generator,
+            })),
+            S::Atom(Atom::Match(m)) => S::Atom(Atom::Match(Box::new(self.match_arms(*m)))),
+            S::Atom(a) => S::Atom(a),
+            S::Cons(Op::Interpolate, parts) => {
+                let parts: Vec<S> = parts.into_iter().map(|p| self.expr(p)).collect();
+                let literals: Option<Vec<Atom>> = parts
+                    .iter()
+                    .map(|p| match p {
+                        S::Atom(a) if is_literal(a) => Some(a.clone()),
+                        _ => None,
+                    })
+                    .collect();
+
+                match literals {
+                    Some(literals) => S::Atom(interpolate(literals.into_iter())),
+                    None => S::Cons(Op::Interpolate, parts),
+                }
+            }
+            S::Cons(op, xs) => {
+                let xs: Vec<S> = xs.into_iter().map(|x| self.expr(x)).collect();
+                match xs.as_slice() {
+                    [S::Atom(a)] if can_fold_unary(op, a) => S::Atom(unary_op(&op, a.clone())),
+                    // the rhs never runs, whatever it is
+                    [S::Atom(Atom::Bool(b)), _] if op == Op::Or && *b || op == Op::And && !*b => {
+                        S::Atom(Atom::Bool(*b))
+                    }
+                    [S::Atom(a), S::Atom(b)] if can_fold_binary(op, a, b) => {
+                        S::Atom(binary_op(&op, a.clone(), b.clone()))
+                    }
+                    _ => S::Cons(op, xs),
+                }
+            }
+        }
+    }
+}
+
+// Since variables are dynamically scoped, a name that is declared exactly
+// once in the whole program (counting function names and parameters) and
+// never reassigned always refers to that declaration.
+fn propagate_constants(block: Block) -> Block {
+    let mut declared = BTreeMap::new();
+    let mut assigned = BTreeSet::new();
+    count_block(&block, &mut declared, &mut assigned);
+
+    let mut folder = Folder::default();
+    let mut statements = Vec::with_capacity(block.statements.len());
+    for stmt in block.statements {
+        let stmt = folder.stmt(stmt);
+        if let Stmt::Dec(Declaration {
+            lhs,
+            rhs: S::Atom(a),
+            alias: true,
+            ..
+        }) = &stmt
+        {
+            if is_literal(a) && declared[lhs] == 1 && !assigned.contains(lhs) {
+                folder.consts.insert(lhs.clone(), a.clone());
+            }
+        }
+        statements.push(stmt);
+    }
+
+    Block::new(statements)
+}
+
+fn count_block(
+    block: &Block,
+    declared: &mut BTreeMap<String, usize>,
+    assigned: &mut BTreeSet<String>,
+) {
+    for stmt in block.statements.iter() {
+        match stmt {
+            Stmt::ExprStmt(expr) | Stmt::PrintStmt(expr) | Stmt::Yield(expr) => {
+                count_expr(expr, declared, assigned)
+            }
+            Stmt::Dec(Declaration {
+                lhs, rhs, alias, ..
+            }) => {
+                if *alias {
+                    *declared.entry(lhs.clone()).or_insert(0) += 1;
+                } else {
+                    assigned.insert(lhs.clone());
+                }
+                count_expr(rhs, declared, assigned);
+            }
+            Stmt::Destructure(pattern, rhs) => {
+                for name in pattern.names() {
+                    *declared.entry(name.to_string()).or_insert(0) += 1;
+                }
+                count_expr(rhs, declared, assigned);
+            }
+            Stmt::IfStmt(If {
+                cond,
+                then_block,
+                else_block,
+            }) => {
+                count_expr(cond, declared, assigned);
+                count_block(then_block, declared, assigned);
+                count_block(else_block, declared, assigned);
+            }
+            Stmt::WhileStmt(While { cond, loop_block }) => {
+                count_expr(cond, declared, assigned);
+                count_block(loop_block, declared, assigned);
+            }
+            Stmt::MatchStmt(match_data) => count_match(match_data, declared, assigned),
+            Stmt::Block(b) => count_block(b, declared, assigned),
+            Stmt::Break => {}
+        }
+    }
+}
+
+fn count_match(
+    Match { value, arms }: &Match,
+    declared: &mut BTreeMap<String, usize>,
+    assigned: &mut BTreeSet<String>,
+) {
+    count_expr(value, declared, assigned);
+    for arm in arms {
+        // alternatives bind the same names, so only count them once
+        for name in arm.patterns[0].names() {
+            *declared.entry(name.to_string()).or_insert(0) += 1;
+        }
+        if let Some(guard) = &arm.guard {
+            count_expr(guard, declared, assigned);
+        }
+        count_block(&arm.block, declared, assigned);
+    }
+}
+
+fn count_expr(expr: &S, declared: &mut BTreeMap<String, usize>, assigned: &mut BTreeSet<String>) {
+    match expr {
+        S::Atom(Atom::Array(elems) | Atom::Tuple(elems)) => {
+            elems.iter().for_each(|e| count_expr(e, declared, assigned));
+        }
+        S::Atom(Atom::FnCall(FunctionCall { args, .. })) => {
+            args.iter().for_each(|a| count_expr(a, declared, assigned));
+        }
+        S::Atom(Atom::Function(FunctionData {
+            arg_names,
+            fn_block,
+            ..
+        })) => {
+            for arg_name in arg_names {
+                *declared.entry(arg_name.clone()).or_insert(0) += 1;
+            }
+            count_block(fn_block, declared, assigned);
+        }
+        S::Atom(Atom::Match(m)) => count_match(m, declared, assigned),
+        S::Atom(_) => {}
+        S::Cons(_, xs) => xs.iter().for_each(|x| count_expr(x, declared, assigned)),
+    }
+}
+
+#[cfg(test)]
+mod optimize_tests {
+    use super::*;
+    use crate::parser::{parse_block, Lexer};
+    use crate::scan_tokens;
+
+    fn optimize_str(s: &str, opt_level: u8) -> Vec<String> {
+        let block = parse_block(&mut Lexer::new(scan_tokens(s).unwrap()));
+        optimize(block, opt_level)
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::ExprStmt(expr) => expr.to_string(),
+                Stmt::Dec(dec) => format!("{} = {}", dec.lhs, dec.rhs),
+                Stmt::Block(b) => format!("block of {}", b.statements.len()),
+                Stmt::IfStmt(_) => "if".to_string(),
+                Stmt::WhileStmt(_) => "while".to_string(),
+                _ => "other".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(optimize_str("1 + 2 * 3", 1), ["7"]);
+        assert_eq!(
+            optimize_str("600.0 / 2.0 - x", 1),
+            ["(- 300 (Identifier x))"]
+        );
+        assert_eq!(optimize_str("(1 < 2) && !(3 == 4)", 1), ["true"]);
+        assert_eq!(optimize_str("false && f()", 1), ["false"]);
+        assert_eq!(optimize_str("1 < 2 || f()", 1), ["true"]);
+        assert_eq!(optimize_str("true && f()", 1), ["(&& true f([]))"]);
+        assert_eq!(optimize_str("\"a\" + 1", 1), ["a1"]);
+        assert_eq!(
+            optimize_str("\"{1 + 2} {x}\"", 1),
+            ["(interpolate 3   (Identifier x))"]
+        );
+        assert_eq!(optimize_str("\"{1 + 2} {true}\"", 1), ["3 true"]);
+        assert_eq!(optimize_str("~(1 << 4 | 3) & 255", 1), ["236"]);
+        assert_eq!(optimize_str("2 ** 10 // 3", 1), ["341"]);
+        assert_eq!(optimize_str("1 + 2 * 3", 0), ["(+ 1 (* 2 3))"]);
+    }
+
+    #[test]
+    fn test_no_fold() {
+        // these would panic, so they have to be left for runtime
+        assert_eq!(optimize_str("5 % 0", 1), ["(% 5 0)"]);
+        assert_eq!(optimize_str("5 // 0", 1), ["(// 5 0)"]);
+        assert_eq!(optimize_str("1 << 64", 1), ["(<< 1 64)"]);
+        assert_eq!(optimize_str("2 ** 64", 1), ["(** 2 64)"]);
+        assert_eq!(optimize_str("\"a\" < 1", 1), ["(< a 1)"]);
+        assert_eq!(optimize_str("true + 1", 1), ["(+ true 1)"]);
+    }
+
+    #[test]
+    fn test_dead_branches() {
+        assert_eq!(
+            optimize_str("if (1 > 2) { 1 } else { 2\n3 }", 1),
+            ["block of 2"]
+        );
+        assert_eq!(optimize_str("if (x > 2) { 1 }", 1), ["if"]);
+        assert_eq!(optimize_str("while (false) { 1 }", 1), ["block of 0"]);
+        assert_eq!(optimize_str("while (1 < 2) { break }", 1), ["while"]);
+    }
+
+    #[test]
+    fn test_propagate() {
+        assert_eq!(
+            optimize_str("let a = 600.0\nlet b = a / 2.0\nb + 1", 2),
+            ["a = 600", "b = 300", "301"]
+        );
+        // reassigned or shadowed variables aren't constant
+        assert_eq!(
+            optimize_str("let a = 1\na = 2\na + 1", 2),
+            ["a = 1", "a = 2", "(+ (Identifier a) 1)"]
+        );
+        assert_eq!(
+            optimize_str("let a = 1\nfn f(a) { a }\na + 1", 2),
+            ["a = 1", "f = FunctionData", "(+ (Identifier a) 1)"]
+        );
+        assert_eq!(
+            optimize_str("let a = 1\na + 1", 1),
+            ["a = 1", "(+ (Identifier a) 1)"]
+        );
+    }
+}
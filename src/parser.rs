@@ -13,6 +13,9 @@ mod fn_parse;
 mod for_parse;
 mod ident_parse;
 mod if_parse;
+mod interpolation_parse;
+mod match_parse;
+mod pattern_parse;
 mod while_parse;
 
 // https://matklad.github.io/2020/04/13/simple-but-powerful-pratt-parsing.html
@@ -23,24 +26,35 @@ pub enum S {
     Cons(Op, Vec<S>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Op {
     Plus,
-    PlusAssign,
     Minus,
-    MinusAssign,
     Negate,
     Multiply,
     Divide,
+    FloorDivide,
+    Pow,
     Less,
+    LessEqual,
     Equal,
     Greater,
+    GreaterEqual,
     NotEqual,
     Mod,
     And,
     Or,
     Indexing,
     Access,
+    Interpolate,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    In,
+    Range,
 }
 
 impl fmt::Display for Op {
@@ -51,20 +65,31 @@ impl fmt::Display for Op {
             match self {
                 Op::Negate => "!",
                 Op::Plus => "+",
-                Op::PlusAssign => "+=",
                 Op::Minus => "-",
-                Op::MinusAssign => "-=",
                 Op::Multiply => "*",
                 Op::Divide => "/",
+                Op::FloorDivide => "//",
+                Op::Pow => "**",
                 Op::Less => "<",
+                Op::LessEqual => "<=",
                 Op::Equal => "==",
                 Op::Greater => ">",
+                Op::GreaterEqual => ">=",
                 Op::NotEqual => "!=",
                 Op::Mod => "%",
                 Op::And => "&&",
                 Op::Or => "||",
                 Op::Indexing => "[]",
                 Op::Access => "access",
+                Op::Interpolate => "interpolate",
+                Op::BitAnd => "&",
+                Op::BitOr => "|",
+                Op::BitXor => "^",
+                Op::BitNot => "~",
+                Op::ShiftLeft => "<<",
+                Op::ShiftRight => ">>",
+                Op::In => "in",
+                Op::Range => "..",
             }
         )
     }
@@ -109,6 +134,16 @@ impl Lexer {
             .unwrap_or_else(|| Token::from_ty(TokenType::EOF))
     }
 
+    /// Looks `n` tokens past the next one without consuming anything.
+    fn peek_nth(&mut self, n: usize) -> Token {
+        self.tokens
+            .iter()
+            .rev()
+            .nth(n)
+            .cloned()
+            .unwrap_or_else(|| Token::from_ty(TokenType::EOF))
+    }
+
     pub fn is_empty(&self) -> bool {
         self.tokens.is_empty()
     }
@@ -170,7 +205,7 @@ pub fn parse_stmt(lexer: &mut Lexer) -> Option<Stmt> {
             ty: TokenType::Let, ..
         } => {
             lexer.next();
-            Some(Stmt::Dec(assignment_parse::parse_declaration(lexer)))
+            Some(assignment_parse::parse_declaration(lexer))
         }
         Token {
             ty: TokenType::Identifier,
@@ -186,6 +221,10 @@ pub fn parse_stmt(lexer: &mut Lexer) -> Option<Stmt> {
         Token {
             ty: TokenType::For, ..
         } => Some(for_parse::parse_for(lexer)),
+        Token {
+            ty: TokenType::Match,
+            ..
+        } => Some(Stmt::MatchStmt(match_parse::parse_match(lexer))),
         Token {
             ty: TokenType::Break,
             ..
@@ -193,6 +232,13 @@ pub fn parse_stmt(lexer: &mut Lexer) -> Option<Stmt> {
             lexer.next();
             Some(Stmt::Break)
         }
+        Token {
+            ty: TokenType::Yield,
+            ..
+        } => {
+            lexer.next();
+            Some(Stmt::Yield(parse_expr(lexer)))
+        }
         Token {
             ty: TokenType::Function,
             ..
@@ -202,7 +248,7 @@ pub fn parse_stmt(lexer: &mut Lexer) -> Option<Stmt> {
                 lhs: fn_name,
                 rhs: S::Atom(Atom::Function(fn_data)),
                 alias: true,
-                plus_or_minus: None,
+                op: None,
             }))
         }
         _t => Some(Stmt::ExprStmt(parse_expr(lexer))),
@@ -210,19 +256,25 @@ pub fn parse_stmt(lexer: &mut Lexer) -> Option<Stmt> {
 }
 
 pub fn parse_expr(lexer: &mut Lexer) -> S {
-    expr_bp(lexer, 0, 0)
+    expr_bp(lexer, 0)
 }
 
 fn is_prefix_op(t: &TokenType) -> bool {
-    matches!(t, TokenType::Minus | TokenType::Bang)
+    matches!(t, TokenType::Minus | TokenType::Bang | TokenType::Tilde)
 }
 
-fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
+fn expr_bp(lexer: &mut Lexer, bp: u8) -> S {
     let nx = lexer.next();
     let mut lhs = match nx.ty {
         TokenType::Literal(a) => S::Atom(a),
+        TokenType::Interpolation(parts) => interpolation_parse::parse_interpolation(parts),
         TokenType::True => S::Atom(Atom::Bool(true)),
         TokenType::False => S::Atom(Atom::Bool(false)),
+        TokenType::Nil => S::Atom(Atom::Nil),
+        TokenType::Match => {
+            lexer.prepend(nx);
+            S::Atom(Atom::Match(Box::new(match_parse::parse_match_expr(lexer))))
+        }
         TokenType::Identifier => match lexer.peek().ty {
             TokenType::LParen => {
                 let args = fn_parse::parse_fn_call_args(lexer);
@@ -237,18 +289,34 @@ fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
             let op = match t {
                 TokenType::Minus => Op::Minus,
                 TokenType::Bang => Op::Negate,
+                TokenType::Tilde => Op::BitNot,
                 _ => unreachable!(),
             };
             let ((), r_bp) = prefix_binding_power(&op);
-            let rhs = expr_bp(lexer, r_bp, paren_depth);
+            let rhs = expr_bp(lexer, r_bp);
             S::Cons(op, vec![rhs])
         }
+        // `()` and `(a,)` are tuples, `(a)` is just `a`
+        TokenType::LParen if lexer.peek().ty == TokenType::RParen => {
+            lexer.next();
+            S::Atom(Atom::Tuple(vec![]))
+        }
         TokenType::LParen => {
-            let lhs = expr_bp(lexer, 0, paren_depth + 1);
-            if lexer.next().ty == TokenType::RParen {
-                lhs
-            } else {
-                panic!("Unbalanced left parenthesis")
+            let lhs = expr_bp(lexer, 0);
+            match lexer.next().ty {
+                TokenType::RParen => lhs,
+                TokenType::Comma => {
+                    let mut elems = vec![lhs];
+                    while lexer.peek().ty != TokenType::RParen {
+                        elems.push(parse_expr(lexer));
+                        if lexer.peek().ty == TokenType::Comma {
+                            lexer.next();
+                        }
+                    }
+                    lexer.next();
+                    S::Atom(Atom::Tuple(elems))
+                }
+                _ => panic!("Unbalanced left parenthesis"),
             }
         }
         TokenType::LBracket => {
@@ -282,25 +350,31 @@ fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
                 break;
             }
             TokenType::Plus => Op::Plus,
-            TokenType::PlusAssign => Op::PlusAssign,
             TokenType::Minus => Op::Minus,
-            TokenType::MinusAssign => Op::MinusAssign,
             TokenType::Slash => Op::Divide,
             TokenType::Star => Op::Multiply,
+            TokenType::SlashSlash => Op::FloorDivide,
+            TokenType::StarStar => Op::Pow,
+            TokenType::Ampersand => Op::BitAnd,
+            TokenType::Pipe => Op::BitOr,
+            TokenType::Caret => Op::BitXor,
+            TokenType::LessLess => Op::ShiftLeft,
+            TokenType::GreaterGreater => Op::ShiftRight,
             TokenType::Bang => Op::Negate,
             TokenType::Equal => Op::Equal,
             TokenType::Less => Op::Less,
             TokenType::Greater => Op::Greater,
+            TokenType::LessEqual => Op::LessEqual,
+            TokenType::GreaterEqual => Op::GreaterEqual,
             TokenType::BangEqual => Op::NotEqual,
             TokenType::Percent => Op::Mod,
             TokenType::And => Op::And,
             TokenType::Or => Op::Or,
             TokenType::LBracket => Op::Indexing,
             TokenType::Dot => Op::Access,
-            TokenType::RParen | TokenType::RBrace => {
-                // if paren_depth < 1 {
-                //     panic!("Unbalanced right parenthesis");
-                // }
+            TokenType::In => Op::In,
+            TokenType::DotDot => Op::Range,
+            TokenType::RParen | TokenType::LBrace | TokenType::RBrace | TokenType::FatArrow => {
                 break;
             }
             t => unimplemented!("Operator: {:?}, lhs: {:?}", t, lhs), // could be panic
@@ -329,7 +403,7 @@ fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
         }
 
         lexer.next();
-        let rhs = expr_bp(lexer, r_bp, paren_depth);
+        let rhs = expr_bp(lexer, r_bp);
 
         lhs = S::Cons(op, vec![lhs, rhs]);
     }
@@ -337,9 +411,15 @@ fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
     lhs
 }
 
+// From loosest to tightest: `||`, `&&`, equality, comparisons and `in`,
+// `..`, `|`, `^`, `&`, shifts, `+ -`, `* / // %`, prefix operators, `**`,
+// then indexing and field access. `**` is right associative and every other
+// infix operator is left associative. Like in Python, `-2 ** 2` is
+// `-(2 ** 2)`.
+
 fn postfix_binding_power(op: &Op) -> Option<(u8, ())> {
     let res = match op {
-        Op::Indexing => (9, ()),
+        Op::Indexing => (27, ()),
         _ => return None,
     };
     Some(res)
@@ -347,20 +427,26 @@ fn postfix_binding_power(op: &Op) -> Option<(u8, ())> {
 
 fn infix_binding_power(op: &Op) -> (u8, u8) {
     match op {
-        Op::Access => (8, 9),
-        Op::Plus | Op::Minus => (4, 5),
-        Op::Multiply | Op::Divide => (6, 7),
-        Op::Mod => (2, 3),
-        Op::And | Op::Or => (1, 2),
-        Op::Equal | Op::NotEqual | Op::Less | Op::Greater => (0, 1),
+        Op::Or => (1, 2),
+        Op::And => (3, 4),
+        Op::Equal | Op::NotEqual => (5, 6),
+        Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual | Op::In => (7, 8),
+        Op::Range => (9, 10),
+        Op::BitOr => (11, 12),
+        Op::BitXor => (13, 14),
+        Op::BitAnd => (15, 16),
+        Op::ShiftLeft | Op::ShiftRight => (17, 18),
+        Op::Plus | Op::Minus => (19, 20),
+        Op::Multiply | Op::Divide | Op::FloorDivide | Op::Mod => (21, 22),
+        Op::Pow => (25, 24),
+        Op::Access => (28, 29),
         _ => panic!("bad op {:?}", op),
     }
 }
 
 fn prefix_binding_power(op: &Op) -> ((), u8) {
     match op {
-        Op::Minus => ((), 9),
-        Op::Negate => ((), 10),
+        Op::Minus | Op::Negate | Op::BitNot => ((), 23),
         _ => panic!("bad op: {:?}", op),
     }
 }
@@ -372,7 +458,7 @@ mod parser_tests {
     use crate::Lexer;
 
     fn str_to_expr(s: &str) -> S {
-        let tokens = scan_tokens(s);
+        let tokens = scan_tokens(s).unwrap();
         let mut lexer = Lexer::new(tokens);
         parse_expr(&mut lexer)
     }
@@ -396,4 +482,111 @@ mod parser_tests {
             "(5 + 5) * 4" => "(* (+ 5 5) 4)"
         );
     }
+
+    #[test]
+    fn test_interpolation() {
+        test_expr!(
+            "\"{x}\"" => "(interpolate (Identifier x))",
+            "\"x = {x + 1}!\"" => "(interpolate x =  (+ (Identifier x) 1) !)",
+            "\"{a[\"{b}\"]}\"" => "(interpolate ([] (Identifier a) (interpolate (Identifier b))))",
+            "\"\\{x}\"" => "{x}"
+        );
+    }
+
+    #[test]
+    fn test_precedence() {
+        // each level against the ones next to it, in both orders
+        test_expr!(
+            "a || b && c" => "(|| (Identifier a) (&& (Identifier b) (Identifier c)))",
+            "a && b || c" => "(|| (&& (Identifier a) (Identifier b)) (Identifier c))",
+            "a && b == c" => "(&& (Identifier a) (== (Identifier b) (Identifier c)))",
+            "a == b && c" => "(&& (== (Identifier a) (Identifier b)) (Identifier c))",
+            "a != b < c" => "(!= (Identifier a) (< (Identifier b) (Identifier c)))",
+            "a <= b == c" => "(== (<= (Identifier a) (Identifier b)) (Identifier c))",
+            "a > b + c" => "(> (Identifier a) (+ (Identifier b) (Identifier c)))",
+            "a - b >= c" => "(>= (- (Identifier a) (Identifier b)) (Identifier c))",
+            "a + b % c" => "(+ (Identifier a) (% (Identifier b) (Identifier c)))",
+            "a % b - c" => "(- (% (Identifier a) (Identifier b)) (Identifier c))",
+            "a * -b" => "(* (Identifier a) (- (Identifier b)))",
+            "-a * b" => "(* (- (Identifier a)) (Identifier b))",
+            "!a == b" => "(== (! (Identifier a)) (Identifier b))",
+            "-a[0]" => "(- ([] (Identifier a) 0))",
+            "!a[0] && b[1]" => "(&& (! ([] (Identifier a) 0)) ([] (Identifier b) 1))",
+            "a in b..c" => "(in (Identifier a) (.. (Identifier b) (Identifier c)))",
+            "a..b | c" => "(.. (Identifier a) (| (Identifier b) (Identifier c)))",
+            "a[b + 1..c]" => "([] (Identifier a) (.. (+ (Identifier b) 1) (Identifier c)))",
+            "a in b == c" => "(== (in (Identifier a) (Identifier b)) (Identifier c))",
+            "a + b in c" => "(in (+ (Identifier a) (Identifier b)) (Identifier c))"
+        );
+
+        // across several levels at once
+        test_expr!(
+            "a || b && c == d < e + f * -g" =>
+                "(|| (Identifier a) (&& (Identifier b) (== (Identifier c) (< (Identifier d) (+ (Identifier e) (* (Identifier f) (- (Identifier g))))))))",
+            "-g * f + e < d == c && b || a" =>
+                "(|| (&& (== (< (+ (* (- (Identifier g)) (Identifier f)) (Identifier e)) (Identifier d)) (Identifier c)) (Identifier b)) (Identifier a))",
+            "i < len(arr) && arr[i] > 0" =>
+                "(&& (< (Identifier i) len([Atom(Identifier(\"arr\"))])) (> ([] (Identifier arr) (Identifier i)) 0))",
+            "(a || b) && c" => "(&& (|| (Identifier a) (Identifier b)) (Identifier c))",
+            "a * (b + c) % d" => "(% (* (Identifier a) (+ (Identifier b) (Identifier c))) (Identifier d))"
+        );
+    }
+
+    #[test]
+    fn test_associativity() {
+        test_expr!(
+            "a - b - c" => "(- (- (Identifier a) (Identifier b)) (Identifier c))",
+            "a + b - c + d" => "(+ (- (+ (Identifier a) (Identifier b)) (Identifier c)) (Identifier d))",
+            "a / b / c" => "(/ (/ (Identifier a) (Identifier b)) (Identifier c))",
+            "a % b * c / d" => "(/ (* (% (Identifier a) (Identifier b)) (Identifier c)) (Identifier d))",
+            "a < b < c" => "(< (< (Identifier a) (Identifier b)) (Identifier c))",
+            "a == b != c" => "(!= (== (Identifier a) (Identifier b)) (Identifier c))",
+            "a && b && c" => "(&& (&& (Identifier a) (Identifier b)) (Identifier c))",
+            "a || b || c" => "(|| (|| (Identifier a) (Identifier b)) (Identifier c))",
+            "--a" => "(- (- (Identifier a)))",
+            "!!a" => "(! (! (Identifier a)))",
+            "a[0][1]" => "([] ([] (Identifier a) 0) 1)",
+            "a ** b ** c" => "(** (Identifier a) (** (Identifier b) (Identifier c)))",
+            "-a ** b" => "(- (** (Identifier a) (Identifier b)))",
+            "a ** -b" => "(** (Identifier a) (- (Identifier b)))",
+            "a * b ** c" => "(* (Identifier a) (** (Identifier b) (Identifier c)))",
+            "~a & b" => "(& (~ (Identifier a)) (Identifier b))",
+            "a[0] ** 2" => "(** ([] (Identifier a) 0) 2)"
+        );
+    }
+
+    #[test]
+    fn test_every_operator_pair() {
+        // loosest first
+        let levels: &[&[&str]] = &[
+            &["||"],
+            &["&&"],
+            &["==", "!="],
+            &["<", "<=", ">", ">=", "in"],
+            &[".."],
+            &["|"],
+            &["^"],
+            &["&"],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "//", "%"],
+        ];
+
+        for (i, level_1) in levels.iter().enumerate() {
+            for (j, level_2) in levels.iter().enumerate() {
+                for op_1 in level_1.iter() {
+                    for op_2 in level_2.iter() {
+                        let (a, b, c) = ("(Identifier a)", "(Identifier b)", "(Identifier c)");
+                        let expected = if i < j {
+                            format!("({} {} ({} {} {}))", op_1, a, op_2, b, c)
+                        } else {
+                            format!("({} ({} {} {}) {})", op_2, op_1, a, b, c)
+                        };
+                        let input = format!("a {} b {} c", op_1, op_2);
+                        assert_eq!(str_to_expr(&input).to_string(), expected, "{}", input);
+                    }
+                }
+            }
+        }
+    }
 }
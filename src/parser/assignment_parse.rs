@@ -1,20 +1,20 @@
-use crate::{parse_expr, statement::Declaration, Lexer, Token, TokenType};
+use crate::eval::pattern::Pattern;
+use crate::{parse_expr, statement::Declaration, statement::Stmt, Lexer, TokenType};
 
-pub fn parse_declaration(lexer: &mut Lexer) -> Declaration {
-    if let Token {
-        ty: TokenType::Identifier,
-        lexeme: name,
-        ..
-    } = lexer.next()
-    {
-        assert_eq!(lexer.next().ty, TokenType::Assign);
-        Declaration {
+use super::pattern_parse::parse_pattern;
+
+pub fn parse_declaration(lexer: &mut Lexer) -> Stmt {
+    let pattern = parse_pattern(lexer);
+    assert_eq!(lexer.next().ty, TokenType::Assign);
+    let rhs = parse_expr(lexer);
+
+    match pattern {
+        Pattern::Name(name) => Stmt::Dec(Declaration {
             lhs: name,
-            rhs: parse_expr(lexer),
+            rhs,
             alias: true,
-            plus_or_minus: None,
-        }
-    } else {
-        panic!("error on parsing assignment");
+            op: None,
+        }),
+        pattern => Stmt::Destructure(pattern, rhs),
     }
 }
@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use crate::eval::atom::FunctionData;
 use crate::Token;
 use crate::S;
@@ -22,13 +24,14 @@ pub fn parse_fn_dec(lexer: &mut Lexer) -> (String, FunctionData) {
     let arg_names = parse_fn_dec_args(lexer);
 
     assert_eq!(lexer.next().ty, TokenType::LBrace);
-    let fn_block = parse_block(lexer);
+    let fn_block = Rc::new(parse_block(lexer));
     assert_eq!(lexer.next().ty, TokenType::RBrace);
 
     (
         fn_name,
         FunctionData {
             arg_names,
+            generator: fn_block.yields(),
             fn_block,
         },
     )
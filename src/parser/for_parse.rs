@@ -1,13 +1,43 @@
-use crate::statement::Stmt;
+use crate::eval::atom::FunctionCall;
+use crate::eval::pattern::Pattern;
+use crate::statement::{Declaration, Stmt};
 use crate::Atom;
 use crate::{block::Block, parse_stmt};
 use crate::{parse_block, parse_expr, statement::While, Lexer, TokenType};
 
+use super::pattern_parse::parse_pattern;
 use super::S;
 
 pub fn parse_for(lexer: &mut Lexer) -> Stmt {
     lexer.next();
 
+    if is_for_in(lexer) {
+        let pattern = parse_pattern(lexer);
+        parse_for_in(lexer, pattern)
+    } else {
+        parse_c_for(lexer)
+    }
+}
+
+// A for-in loop has an `in` right after its pattern, outside of any
+// brackets, while a C-style loop gets to a `;` first. Patterns can be
+// parenthesised too, like `for ((k, v)) in ...`.
+fn is_for_in(lexer: &mut Lexer) -> bool {
+    let mut depth = 0;
+    let mut n = 0;
+    loop {
+        match lexer.peek_nth(n).ty {
+            TokenType::In if depth == 0 => return true,
+            TokenType::LParen | TokenType::LBracket => depth += 1,
+            TokenType::RParen | TokenType::RBracket => depth -= 1,
+            TokenType::Semicolon | TokenType::LBrace | TokenType::EOF => return false,
+            _ => {}
+        }
+        n += 1;
+    }
+}
+
+fn parse_c_for(lexer: &mut Lexer) -> Stmt {
     assert_eq!(lexer.next().ty, TokenType::LParen);
     let init_statement = if lexer.peek().ty != TokenType::Semicolon {
         Some(parse_stmt(lexer))
@@ -49,3 +79,62 @@ pub fn parse_for(lexer: &mut Lexer) -> Stmt {
         Stmt::Block(Block::new(vec![Stmt::WhileStmt(while_stmt)]))
     }
 }
+
+// The name of the hidden iterator can't be written in a script.
+const ITER: &str = "@iter";
+
+/// `for x in xs { body }` becomes
+///
+/// ```text
+/// {
+///     let @iter = iter(xs)
+///     while (has_next(@iter)) {
+///         let x = next(@iter)
+///         body
+///     }
+/// }
+/// ```
+///
+/// With a pattern other than a name, each item is destructured like in a
+/// `let`.
+fn parse_for_in(lexer: &mut Lexer, pattern: Pattern) -> Stmt {
+    assert_eq!(lexer.next().ty, TokenType::In);
+    let iterable = parse_expr(lexer);
+
+    assert_eq!(lexer.next().ty, TokenType::LBrace);
+    let body = parse_block(lexer);
+    assert_eq!(lexer.next().ty, TokenType::RBrace);
+
+    let var = |name: &str| S::Atom(Atom::Identifier(name.to_string()));
+    let call = |name: &str, arg: S| {
+        S::Atom(Atom::FnCall(FunctionCall {
+            name: name.to_string(),
+            args: vec![arg],
+        }))
+    };
+    let let_stmt = |lhs: &str, rhs: S| {
+        Stmt::Dec(Declaration {
+            lhs: lhs.to_string(),
+            rhs,
+            alias: true,
+            op: None,
+        })
+    };
+
+    let item = call("next", var(ITER));
+    let mut statements = match pattern {
+        Pattern::Name(name) => vec![let_stmt(&name, item)],
+        pattern => vec![Stmt::Destructure(pattern, item)],
+    };
+    statements.extend(body.statements);
+
+    let while_stmt = While {
+        cond: call("has_next", var(ITER)),
+        loop_block: Block::new(statements),
+    };
+
+    Stmt::Block(Block::new(vec![
+        let_stmt(ITER, call("iter", iterable)),
+        Stmt::WhileStmt(while_stmt),
+    ]))
+}
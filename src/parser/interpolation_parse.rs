@@ -0,0 +1,27 @@
+use crate::eval::atom::Atom;
+use crate::{parse_expr, Lexer, Op, StrPart, S};
+
+pub fn parse_interpolation(parts: Vec<StrPart>) -> S {
+    let parts = parts
+        .into_iter()
+        .filter(|part| !matches!(part, StrPart::Str(s) if s.is_empty()))
+        .map(|part| match part {
+            StrPart::Str(s) => S::Atom(Atom::Str(s)),
+            StrPart::Expr(tokens) if tokens.is_empty() => {
+                panic!("Empty expression in interpolated string")
+            }
+            StrPart::Expr(tokens) => {
+                let mut lexer = Lexer::new(tokens);
+                let expr = parse_expr(&mut lexer);
+                assert!(
+                    lexer.is_empty(),
+                    "Unexpected tokens after {} in interpolated string",
+                    expr
+                );
+                expr
+            }
+        })
+        .collect();
+
+    S::Cons(Op::Interpolate, parts)
+}
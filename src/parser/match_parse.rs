@@ -0,0 +1,266 @@
+use crate::block::Block;
+use crate::eval::atom::Atom;
+use crate::eval::pattern::Pattern;
+use crate::statement::{Arm, If, Match, Stmt, While};
+use crate::{parse_block, parse_expr, parse_stmt, Lexer, TokenType};
+
+use super::pattern_parse::parse_pattern;
+
+/// Parses `match value { pattern | pattern if guard => body, ... }`, where a
+/// body is either a block or a single statement. Arms that can never be
+/// reached because of an earlier one are rejected, and so are matches which
+/// some value of the type their patterns imply gets through, see
+/// `exhaustive`.
+pub fn parse_match(lexer: &mut Lexer) -> Match {
+    lexer.next();
+    let value = parse_expr(lexer);
+    assert_eq!(lexer.next().ty, TokenType::LBrace);
+
+    let mut arms: Vec<Arm> = vec![];
+    loop {
+        while matches!(
+            lexer.peek().ty,
+            TokenType::NewLine | TokenType::Semicolon | TokenType::Comma
+        ) {
+            lexer.next();
+        }
+        if lexer.peek().ty == TokenType::RBrace {
+            lexer.next();
+            break;
+        }
+
+        let arm = parse_arm(lexer);
+        for pattern in &arm.patterns {
+            let covered = arms
+                .iter()
+                .filter(|earlier| earlier.guard.is_none())
+                .flat_map(|earlier| &earlier.patterns)
+                .find(|earlier| covers(earlier, pattern));
+            if let Some(earlier) = covered {
+                panic!(
+                    "Unreachable match arm {}, {} already matches",
+                    pattern, earlier
+                );
+            }
+        }
+        arms.push(arm);
+    }
+
+    let rows: Vec<Vec<Pattern>> = arms
+        .iter()
+        .filter(|arm| arm.guard.is_none())
+        .flat_map(|arm| arm.patterns.iter().map(|pattern| vec![pattern.clone()]))
+        .collect();
+    if !exhaustive(&rows) {
+        panic!("The match doesn't cover every value, add a `_` arm");
+    }
+
+    Match { value, arms }
+}
+
+/// Parses a `match` used as an expression, like `let y = match x { ... }`.
+/// It evaluates to the arm it runs, which can't leave it with a `break` or
+/// `yield`.
+pub fn parse_match_expr(lexer: &mut Lexer) -> Match {
+    let match_data = parse_match(lexer);
+    if match_data.arms.iter().any(|arm| escapes(&arm.block, false)) {
+        panic!("A match expression can't break out of a loop or yield");
+    }
+    match_data
+}
+
+// whether running the block could `break` out of a loop around it or
+// `yield`, not counting functions declared in it
+fn escapes(block: &Block, in_loop: bool) -> bool {
+    block.statements.iter().any(|stmt| match stmt {
+        Stmt::Break => !in_loop,
+        Stmt::Yield(_) => true,
+        Stmt::IfStmt(If {
+            then_block,
+            else_block,
+            ..
+        }) => escapes(then_block, in_loop) || escapes(else_block, in_loop),
+        Stmt::WhileStmt(While { loop_block, .. }) => escapes(loop_block, true),
+        Stmt::MatchStmt(Match { arms, .. }) => arms.iter().any(|arm| escapes(&arm.block, in_loop)),
+        Stmt::Block(b) => escapes(b, in_loop),
+        _ => false,
+    })
+}
+
+fn parse_arm(lexer: &mut Lexer) -> Arm {
+    let mut patterns = vec![parse_pattern(lexer)];
+    while lexer.peek().ty == TokenType::Pipe {
+        lexer.next();
+        patterns.push(parse_pattern(lexer));
+    }
+
+    let names = sorted_names(&patterns[0]);
+    if patterns[1..].iter().any(|p| sorted_names(p) != names) {
+        panic!("Every alternative of a match arm has to bind the same variables");
+    }
+
+    let guard = if lexer.peek().ty == TokenType::If {
+        lexer.next();
+        Some(parse_expr(lexer))
+    } else {
+        None
+    };
+    assert_eq!(lexer.next().ty, TokenType::FatArrow);
+
+    let block = if lexer.peek().ty == TokenType::LBrace {
+        lexer.next();
+        let block = parse_block(lexer);
+        assert_eq!(lexer.next().ty, TokenType::RBrace);
+        block
+    } else {
+        match parse_stmt(lexer) {
+            Some(stmt) => Block::new(vec![stmt]),
+            None => panic!("Missing the body of a match arm"),
+        }
+    };
+
+    Arm {
+        patterns,
+        guard,
+        block,
+    }
+}
+
+fn sorted_names(pattern: &Pattern) -> Vec<&str> {
+    let mut names = pattern.names();
+    names.sort_unstable();
+    names
+}
+
+// whether everything `later` matches is already matched by `earlier`
+fn covers(earlier: &Pattern, later: &Pattern) -> bool {
+    match (earlier, later) {
+        (Pattern::Wildcard | Pattern::Name(_), _) => true,
+        (Pattern::Literal(a), Pattern::Literal(b)) => a == b,
+        (Pattern::Tuple(a), Pattern::Tuple(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| covers(a, b))
+        }
+        _ => false,
+    }
+}
+
+// The values a pattern can tell apart without binding them. Other literals,
+// like numbers and strings, have too many values to list, and `nil` doesn't
+// cover a variable that's only nil for now.
+#[derive(Clone, Copy, PartialEq)]
+enum Ctor {
+    Bool(bool),
+    Tuple(usize),
+    // an array of exactly this length
+    Array(usize),
+}
+
+impl Ctor {
+    fn arity(self) -> usize {
+        match self {
+            Ctor::Bool(_) => 0,
+            Ctor::Tuple(n) | Ctor::Array(n) => n,
+        }
+    }
+}
+
+/// Whether every value gets through at least one of the rows of patterns,
+/// assuming each value has the type the patterns in its column imply.
+/// `true` and `false` cover bools, tuple patterns of one length cover those
+/// tuples, and array patterns cover arrays if they cover every length. Each
+/// column of numbers or strings needs a name or `_` somewhere.
+fn exhaustive(rows: &[Vec<Pattern>]) -> bool {
+    let width = match rows.first() {
+        Some(row) => row.len(),
+        None => return false,
+    };
+    if width == 0 {
+        return true;
+    }
+
+    match signature(rows.iter().map(|row| &row[0])) {
+        Some(ctors) => ctors.into_iter().all(|ctor| {
+            let specialized: Vec<Vec<Pattern>> = rows
+                .iter()
+                .filter_map(|row| specialize(row, ctor))
+                .collect();
+            exhaustive(&specialized)
+        }),
+        // only the rows which match anything in this column can help
+        None => {
+            let rest: Vec<Vec<Pattern>> = rows
+                .iter()
+                .filter(|row| is_wildcard(&row[0]))
+                .map(|row| row[1..].to_vec())
+                .collect();
+            exhaustive(&rest)
+        }
+    }
+}
+
+fn is_wildcard(pattern: &Pattern) -> bool {
+    matches!(pattern, Pattern::Wildcard | Pattern::Name(_))
+}
+
+// every constructor of the column's type, if the patterns settle on one
+// whose values can all be listed
+fn signature<'a>(column: impl Iterator<Item = &'a Pattern>) -> Option<Vec<Ctor>> {
+    let heads: Vec<&Pattern> = column.filter(|p| !is_wildcard(p)).collect();
+    let first = heads.first()?;
+
+    match first {
+        Pattern::Literal(Atom::Bool(_))
+            if heads
+                .iter()
+                .all(|p| matches!(p, Pattern::Literal(Atom::Bool(_)))) =>
+        {
+            Some(vec![Ctor::Bool(true), Ctor::Bool(false)])
+        }
+        Pattern::Tuple(items)
+            if heads
+                .iter()
+                .all(|p| matches!(p, Pattern::Tuple(other) if other.len() == items.len())) =>
+        {
+            Some(vec![Ctor::Tuple(items.len())])
+        }
+        Pattern::Array { .. } => {
+            let mut longest = 0;
+            for head in heads {
+                match head {
+                    Pattern::Array { items, .. } => longest = longest.max(items.len()),
+                    _ => return None,
+                }
+            }
+            // arrays longer than every pattern all match the same ones
+            Some((0..=longest + 1).map(Ctor::Array).collect())
+        }
+        _ => None,
+    }
+}
+
+// the row for values built with `ctor`, with the first pattern replaced by
+// the ones for the parts of the value, or `None` if it can't match them
+fn specialize(row: &[Pattern], ctor: Ctor) -> Option<Vec<Pattern>> {
+    let (head, rest) = row.split_first().unwrap();
+    let mut parts = match (head, ctor) {
+        (Pattern::Wildcard | Pattern::Name(_), _) => vec![Pattern::Wildcard; ctor.arity()],
+        (Pattern::Literal(Atom::Bool(a)), Ctor::Bool(b)) if *a == b => vec![],
+        (Pattern::Tuple(items), Ctor::Tuple(n)) if items.len() == n => items.clone(),
+        (Pattern::Array { items, rest: None }, Ctor::Array(n)) if items.len() == n => items.clone(),
+        // only a plain `..` or `..name` is known to match any remainder
+        (
+            Pattern::Array {
+                items,
+                rest: Some(rest),
+            },
+            Ctor::Array(n),
+        ) if items.len() <= n && is_wildcard(rest) => {
+            let mut parts = items.clone();
+            parts.resize(n, Pattern::Wildcard);
+            parts
+        }
+        _ => return None,
+    };
+    parts.extend_from_slice(rest);
+    Some(parts)
+}
@@ -0,0 +1,77 @@
+use crate::eval::atom::Atom;
+use crate::eval::pattern::Pattern;
+use crate::{Lexer, TokenType};
+
+/// Parses a pattern like `x`, `_`, `-1`, `(a, (b, c))` or `[first, ..rest]`.
+/// A single pattern in parentheses is just that pattern, `(a,)` is a tuple
+/// with one element.
+pub fn parse_pattern(lexer: &mut Lexer) -> Pattern {
+    let nx = lexer.next();
+    match nx.ty {
+        TokenType::Identifier if lexer.peek().ty == TokenType::LBrace => {
+            panic!(
+                "Can't match on {} {{ ... }}, there are no structs yet",
+                nx.lexeme
+            )
+        }
+        TokenType::Identifier if nx.lexeme == "_" => Pattern::Wildcard,
+        TokenType::Identifier => Pattern::Name(nx.lexeme),
+        TokenType::Literal(lit) => Pattern::Literal(lit),
+        TokenType::True => Pattern::Literal(Atom::Bool(true)),
+        TokenType::False => Pattern::Literal(Atom::Bool(false)),
+        TokenType::Nil => Pattern::Literal(Atom::Nil),
+        TokenType::Minus => match lexer.next().ty {
+            TokenType::Literal(Atom::Int(n)) => Pattern::Literal(Atom::Int(-n)),
+            TokenType::Literal(Atom::Float(x)) => Pattern::Literal(Atom::Float(-x)),
+            ty => panic!("Invalid pattern -{:?}", ty),
+        },
+        TokenType::LParen => {
+            let mut items = vec![];
+            let mut trailing_comma = false;
+            while lexer.peek().ty != TokenType::RParen {
+                items.push(parse_pattern(lexer));
+                trailing_comma = lexer.peek().ty == TokenType::Comma;
+                if trailing_comma {
+                    lexer.next();
+                }
+            }
+            lexer.next();
+
+            if items.len() == 1 && !trailing_comma {
+                items.pop().unwrap()
+            } else {
+                Pattern::Tuple(items)
+            }
+        }
+        TokenType::LBracket => {
+            let mut items = vec![];
+            let mut rest = None;
+            while lexer.peek().ty != TokenType::RBracket {
+                if lexer.peek().ty == TokenType::DotDot {
+                    lexer.next();
+                    rest = Some(Box::new(match lexer.peek().ty {
+                        TokenType::Identifier => parse_pattern(lexer),
+                        _ => Pattern::Wildcard,
+                    }));
+                    if lexer.peek().ty == TokenType::Comma {
+                        lexer.next();
+                    }
+                    assert_eq!(
+                        lexer.peek().ty,
+                        TokenType::RBracket,
+                        "The rest of an array pattern has to come last"
+                    );
+                    break;
+                }
+
+                items.push(parse_pattern(lexer));
+                if lexer.peek().ty == TokenType::Comma {
+                    lexer.next();
+                }
+            }
+            lexer.next();
+            Pattern::Array { items, rest }
+        }
+        _ => panic!("Invalid pattern {}", nx),
+    }
+}
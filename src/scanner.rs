@@ -1,142 +1,366 @@
+pub mod error;
 pub mod token;
+use error::LexError;
 use token::*;
 
 use crate::eval::atom::Atom;
 
-use itertools::Itertools;
-
-fn skip_comment(source: &[char]) -> Vec<char> {
-    source
-        .iter()
-        .skip_while(|&&c| c != '\n' && c != '\r')
-        .copied()
-        .collect_vec()
+/// A cursor over the source which keeps track of the line and column of the
+/// next character, both starting at 1, and of where the current token began.
+struct Scanner<'s> {
+    source: &'s str,
+    pos: usize,
+    line: usize,
+    col: usize,
+    start: (usize, usize, usize),
+    tokens: Vec<Token>,
+    errors: Vec<LexError>,
 }
 
-fn string_token(source: &[char]) -> Vec<Token> {
-    let cond = |&&c: &&char| c != '\"';
-    let s: String = source.iter().take_while(cond).collect();
-    let remaining = source
-        .iter()
-        .skip_while(cond)
-        .skip(1)
-        .copied()
-        .collect_vec();
-    [
-        vec![Token::new(TokenType::Literal(Atom::Str(s.clone())), s, 0)],
-        token_recurse(&remaining),
-    ]
-    .concat()
-}
+impl<'s> Scanner<'s> {
+    fn new(source: &'s str) -> Self {
+        Scanner {
+            source,
+            pos: 0,
+            line: 1,
+            col: 1,
+            start: (0, 1, 1),
+            tokens: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
 
-fn num_token(source: &[char]) -> Vec<Token> {
-    let cond = |&&c: &&char| c.is_numeric() || c == '.';
-    let s: String = source.iter().take_while(cond).collect();
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
 
-    let n = if let Ok(n) = s.parse::<isize>() {
-        Token::new(TokenType::Literal(Atom::Int(n)), s, 0)
-    } else if let Ok(n) = s.parse::<f64>() {
-        Token::new(TokenType::Literal(Atom::Float(n)), s, 0)
-    } else {
-        Token::new(TokenType::Unknown, s, 0)
-    };
+    fn peek_next(&self) -> Option<char> {
+        self.source[self.pos..].chars().nth(1)
+    }
 
-    let remaining = source.iter().skip_while(cond).copied().collect_vec();
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
 
-    [vec![n], token_recurse(&remaining)].concat()
-}
+    fn advance_while(&mut self, cond: impl Fn(char) -> bool) -> &'s str {
+        let start = self.pos;
+        while self.peek().is_some_and(&cond) {
+            self.advance();
+        }
+        &self.source[start..self.pos]
+    }
 
-fn ident_token(source: &[char]) -> Vec<Token> {
-    fn is_ident_char(c: &&char) -> bool {
-        match &c {
-            c if c.is_alphanumeric() => true,
-            '_' => true,
-            _ => false,
-        }
-    }
-
-    let lex: String = source.iter().take_while(is_ident_char).collect();
-    let remaining = source
-        .iter()
-        .skip_while(is_ident_char)
-        .copied()
-        .collect_vec();
-    macro_rules! add_lexemes {
-        ( $($lex:expr => $ty:expr),* ) => {
-            match lex.as_str() {
-                $( $lex => Token::from_ty($ty), )*
-                _ => Token::new(TokenType::Identifier, lex.clone(), 0),
-            };
-        }
-    }
-
-    let token = add_lexemes!(
-        "let" => TokenType::Let,
-        "if" => TokenType::If,
-        "else" => TokenType::Else,
-        "elif" => TokenType::Elif,
-        "true" => TokenType::True,
-        "false" => TokenType::False,
-        "for" => TokenType::For,
-        "while" => TokenType::While,
-        "fn" => TokenType::Function,
-        "struct" => TokenType::Struct,
-        "break" => TokenType::Break,
-        "print" => TokenType::Print
-    );
-
-    [vec![token], token_recurse(&remaining)].concat()
-}
+    fn matches(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn lexeme(&self) -> &'s str {
+        &self.source[self.start.0..self.pos]
+    }
+
+    fn token(&self, ty: TokenType, lexeme: String) -> Token {
+        let (_, line, col) = self.start;
+        Token::new(ty, lexeme, line, col)
+    }
+
+    fn push(&mut self, res: Result<Token, LexError>) {
+        match res {
+            Ok(token) => self.tokens.push(token),
+            Err(err) => self.errors.push(err),
+        }
+    }
+
+    // errors don't stop the scan, so that they can all be reported at once
+    fn scan(mut self) -> Result<Vec<Token>, Vec<LexError>> {
+        while self.peek().is_some() {
+            self.scan_token();
+        }
+
+        if self.errors.is_empty() {
+            Ok(self.tokens)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn scan_token(&mut self) {
+        self.start = (self.pos, self.line, self.col);
+        let c = match self.advance() {
+            Some(c) => c,
+            None => return,
+        };
+
+        let ty = match c {
+            // a newline at the very end of the source isn't a token
+            '\n' | '\r' if self.peek().is_none() => return,
+            '\n' | '\r' => TokenType::NewLine,
+            '#' => {
+                self.advance_while(|c| c != '\n' && c != '\r');
+                return;
+            }
+            '&' if self.matches('&') => TokenType::And,
+            '|' if self.matches('|') => TokenType::Or,
+            '*' if self.matches('*') => TokenType::StarStar,
+            '/' if self.matches('/') => TokenType::SlashSlash,
+            '+' if self.matches('=') => TokenType::PlusAssign,
+            '-' if self.matches('=') => TokenType::MinusAssign,
+            '*' if self.matches('=') => TokenType::StarAssign,
+            '/' if self.matches('=') => TokenType::SlashAssign,
+            '%' if self.matches('=') => TokenType::PercentAssign,
+            '!' if self.matches('=') => TokenType::BangEqual,
+            '<' if self.matches('=') => TokenType::LessEqual,
+            '>' if self.matches('=') => TokenType::GreaterEqual,
+            '<' if self.matches('<') => TokenType::LessLess,
+            '>' if self.matches('>') => TokenType::GreaterGreater,
+            '=' if self.matches('=') => TokenType::Equal,
+            '=' if self.matches('>') => TokenType::FatArrow,
+            '=' => TokenType::Assign,
+            '<' => TokenType::Less,
+            '>' => TokenType::Greater,
+            '(' => TokenType::LParen,
+            ')' => TokenType::RParen,
+            '{' => TokenType::LBrace,
+            '}' => TokenType::RBrace,
+            ',' => TokenType::Comma,
+            '*' => TokenType::Star,
+            '/' => TokenType::Slash,
+            '-' => TokenType::Minus,
+            '+' => TokenType::Plus,
+            '%' => TokenType::Percent,
+            '.' if self.matches('.') => TokenType::DotDot,
+            '.' => TokenType::Dot,
+            ';' => TokenType::Semicolon,
+            '[' => TokenType::LBracket,
+            ']' => TokenType::RBracket,
+            '!' => TokenType::Bang,
+            '&' => TokenType::Ampersand,
+            '|' => TokenType::Pipe,
+            '^' => TokenType::Caret,
+            '~' => TokenType::Tilde,
+            '\"' => {
+                let res = self.string_token();
+                self.push(res);
+                return;
+            }
+            c if c.is_numeric() => {
+                let res = self.num_token();
+                self.push(res);
+                return;
+            }
+            'r' if self.matches('\"') => {
+                let res = self.raw_string_token();
+                self.push(res);
+                return;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let token = self.ident_token();
+                self.tokens.push(token);
+                return;
+            }
+            c if c.is_whitespace() => return,
+            c => {
+                let (_, line, col) = self.start;
+                self.errors.push(LexError::UnexpectedChar { c, line, col });
+                return;
+            }
+        };
 
-// this could probably be many times faster but
-// the evaluation will always take much longer
-// than the scanner
-fn token_recurse(source: &[char]) -> Vec<Token> {
-    fn recur(ty: TokenType, s: &[char]) -> Vec<Token> {
-        [vec![Token::from_ty(ty)], token_recurse(s)].concat()
-    }
-
-    match &source {
-        [] | ['\n' | '\r'] => vec![],
-        ['#', ..] => token_recurse(skip_comment(source).as_slice()),
-        ['\n' | '\r', xs @ ..] => recur(TokenType::NewLine, xs),
-        ['&', '&', xs @ ..] => recur(TokenType::And, xs),
-        ['|', '|', xs @ ..] => recur(TokenType::Or, xs),
-        ['+', '=', xs @ ..] => recur(TokenType::PlusAssign, xs),
-        ['!', '=', xs @ ..] => recur(TokenType::BangEqual, xs),
-        ['<', '=', xs @ ..] => recur(TokenType::LessEqual, xs),
-        ['>', '=', xs @ ..] => recur(TokenType::GreaterEqual, xs),
-        ['=', '=', xs @ ..] => recur(TokenType::Equal, xs),
-        ['=', xs @ ..] => recur(TokenType::Assign, xs),
-        ['<', xs @ ..] => recur(TokenType::Less, xs),
-        ['>', xs @ ..] => recur(TokenType::Greater, xs),
-        ['(', xs @ ..] => recur(TokenType::LParen, xs),
-        [')', xs @ ..] => recur(TokenType::RParen, xs),
-        ['{', xs @ ..] => recur(TokenType::LBrace, xs),
-        ['}', xs @ ..] => recur(TokenType::RBrace, xs),
-        [',', xs @ ..] => recur(TokenType::Comma, xs),
-        ['*', xs @ ..] => recur(TokenType::Star, xs),
-        ['/', xs @ ..] => recur(TokenType::Slash, xs),
-        ['-', xs @ ..] => recur(TokenType::Minus, xs),
-        ['+', xs @ ..] => recur(TokenType::Plus, xs),
-        ['%', xs @ ..] => recur(TokenType::Percent, xs),
-        ['.', xs @ ..] => recur(TokenType::Dot, xs),
-        [';', xs @ ..] => recur(TokenType::Semicolon, xs),
-        ['[', xs @ ..] => recur(TokenType::LBracket, xs),
-        [']', xs @ ..] => recur(TokenType::RBracket, xs),
-        ['!', xs @ ..] => recur(TokenType::Bang, xs),
-        ['\"', xs @ ..] => string_token(xs),
-        ls @ [c, ..] if c.is_numeric() => num_token(ls),
-        ls @ [c, ..] if c.is_alphabetic() => ident_token(ls),
-        [c, xs @ ..] if c.is_whitespace() => token_recurse(xs),
-        c => panic!("Invalid input {:?}", c),
+        self.tokens.push(self.token(ty, "".to_string()));
+    }
+
+    fn unterminated_string(&self) -> LexError {
+        let (_, line, col) = self.start;
+        LexError::UnterminatedString { line, col }
+    }
+
+    // strings can span multiple lines, so only the end of the source
+    // leaves one unterminated
+    fn string_token(&mut self) -> Result<Token, LexError> {
+        // scanning interpolated expressions moves the start along
+        let start = self.start;
+        let mut parts = Vec::new();
+        let mut s = String::new();
+
+        loop {
+            match self.advance() {
+                Some('\"') => break,
+                Some('\\') => match self.escape() {
+                    Ok(c) => s.push(c),
+                    Err(err) => self.errors.push(err),
+                },
+                Some('{') => {
+                    parts.push(StrPart::Str(std::mem::take(&mut s)));
+                    let errors = self.errors.len();
+                    let tokens = self.interpolated_expr();
+                    self.start = start;
+                    match tokens {
+                        Some(tokens) => parts.push(StrPart::Expr(tokens)),
+                        None => {
+                            // a string in the expression that ran to the end
+                            // of the source is the same error
+                            let nested = self.errors.split_off(errors);
+                            self.errors.extend(
+                                nested.into_iter().filter(|err| {
+                                    !matches!(err, LexError::UnterminatedString { .. })
+                                }),
+                            );
+                            return Err(self.unterminated_string());
+                        }
+                    }
+                }
+                Some(c) => s.push(c),
+                None => {
+                    self.start = start;
+                    return Err(self.unterminated_string());
+                }
+            }
+        }
+
+        if parts.is_empty() {
+            Ok(self.token(TokenType::Literal(Atom::Str(s.clone())), s))
+        } else {
+            parts.push(StrPart::Str(s));
+            Ok(self.token(TokenType::Interpolation(parts), self.lexeme().to_string()))
+        }
+    }
+
+    /// Scans the expression inside `{...}` in a string, up to the matching
+    /// closing brace. Strings nested in the expression are scanned as usual.
+    /// Returns `None` if the source ends first.
+    fn interpolated_expr(&mut self) -> Option<Vec<Token>> {
+        let outer = std::mem::take(&mut self.tokens);
+        let mut depth = 0;
+
+        loop {
+            match self.peek() {
+                Some('}') if depth == 0 => {
+                    self.advance();
+                    break;
+                }
+                Some('{') => depth += 1,
+                Some('}') => depth -= 1,
+                Some(_) => {}
+                None => {
+                    self.tokens = outer;
+                    return None;
+                }
+            }
+            self.scan_token();
+        }
+
+        Some(std::mem::replace(&mut self.tokens, outer))
+    }
+
+    // called after the backslash
+    fn escape(&mut self) -> Result<char, LexError> {
+        let (line, col) = (self.line, self.col - 1);
+        let invalid = |escape: String| LexError::InvalidEscape { escape, line, col };
+
+        match self.advance() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('\\') => Ok('\\'),
+            Some('\"') => Ok('\"'),
+            Some('{') => Ok('{'),
+            Some('}') => Ok('}'),
+            Some('u') if self.matches('{') => {
+                let digits = self.advance_while(|c| c.is_ascii_alphanumeric());
+                if !self.matches('}') {
+                    return Err(invalid(format!("\\u{{{}", digits)));
+                }
+                u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| invalid(format!("\\u{{{}}}", digits)))
+            }
+            Some(c) => Err(invalid(format!("\\{}", c))),
+            None => Err(invalid("\\".to_string())),
+        }
+    }
+
+    // r"..." strings end at the next quote and don't have escapes
+    fn raw_string_token(&mut self) -> Result<Token, LexError> {
+        let s = self.advance_while(|c| c != '\"').to_string();
+        if !self.matches('\"') {
+            return Err(self.unterminated_string());
+        }
+        Ok(self.token(TokenType::Literal(Atom::Str(s.clone())), s))
+    }
+
+    fn num_token(&mut self) -> Result<Token, LexError> {
+        // a `.` followed by another one is a range like `0..n`, not a decimal point
+        while self.peek().is_some_and(char::is_numeric)
+            || (self.peek() == Some('.') && self.peek_next() != Some('.'))
+        {
+            self.advance();
+        }
+        let s = self.lexeme().to_string();
+
+        let ty = if let Ok(n) = s.parse::<isize>() {
+            TokenType::Literal(Atom::Int(n))
+        } else if let Ok(n) = s.parse::<f64>() {
+            TokenType::Literal(Atom::Float(n))
+        } else {
+            let (_, line, col) = self.start;
+            return Err(LexError::MalformedNumber {
+                lexeme: s,
+                line,
+                col,
+            });
+        };
+
+        Ok(self.token(ty, s))
+    }
+
+    fn ident_token(&mut self) -> Token {
+        self.advance_while(|c| c.is_alphanumeric() || c == '_');
+        let lex = self.lexeme();
+
+        macro_rules! add_lexemes {
+            ( $($lex:expr => $ty:expr),* ) => {
+                match lex {
+                    $( $lex => self.token($ty, "".to_string()), )*
+                    _ => self.token(TokenType::Identifier, lex.to_string()),
+                }
+            }
+        }
+
+        add_lexemes!(
+            "let" => TokenType::Let,
+            "if" => TokenType::If,
+            "else" => TokenType::Else,
+            "elif" => TokenType::Elif,
+            "true" => TokenType::True,
+            "false" => TokenType::False,
+            "nil" => TokenType::Nil,
+            "for" => TokenType::For,
+            "in" => TokenType::In,
+            "while" => TokenType::While,
+            "fn" => TokenType::Function,
+            "struct" => TokenType::Struct,
+            "break" => TokenType::Break,
+            "yield" => TokenType::Yield,
+            "match" => TokenType::Match,
+            "print" => TokenType::Print
+        )
     }
 }
 
-pub fn scan_tokens(source: &str) -> Vec<Token> {
-    let slice = source.chars().collect_vec();
-    let token_slice = token_recurse(&slice);
-    token_slice.iter().cloned().collect_vec()
+pub fn scan_tokens(source: &str) -> Result<Vec<Token>, Vec<LexError>> {
+    Scanner::new(source).scan()
 }
 
 #[cfg(test)]
@@ -145,24 +369,31 @@ mod lexer_tests {
     use crate::eval::atom::Atom;
     use crate::scanner::token::*;
 
+    // positions are checked separately
     macro_rules! test_lexer {
         ( $( $input:expr => $expected:expr ),* ) => {
             $(
-                assert_eq!(scan_tokens($input), $expected.collect::<Vec<Token>>());
+                let tokens = scan_tokens($input)
+                    .unwrap()
+                    .into_iter()
+                    .map(|t| Token { line: 0, col: 0, ..t })
+                    .collect::<Vec<Token>>();
+                assert_eq!(tokens, $expected.collect::<Vec<Token>>());
             )*
         }
     }
 
     fn string(s: &str) -> Token {
         Token::new(
-            TokenType::Literal(Atom::Str(s.clone().to_string())),
+            TokenType::Literal(Atom::Str(s.to_string())),
             s.to_string(),
             0,
+            0,
         )
     }
 
     fn identifier(n: &str) -> Token {
-        Token::new(TokenType::Identifier, n.to_string(), 0)
+        Token::new(TokenType::Identifier, n.to_string(), 0, 0)
     }
 
     #[test]
@@ -176,7 +407,241 @@ mod lexer_tests {
             [Token::from_ty(TokenType::GreaterEqual),
             string("hello#notcomment"),
             identifier("identifier_here")
-            ].iter().cloned()
+            ].iter().cloned(),
+            "_ _x" => [identifier("_"), identifier("_x")].iter().cloned()
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let types = |s| {
+            scan_tokens(s)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.ty)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            types("** // & | ^ ~ << >> <= >= && || *= /= =>"),
+            [
+                TokenType::StarStar,
+                TokenType::SlashSlash,
+                TokenType::Ampersand,
+                TokenType::Pipe,
+                TokenType::Caret,
+                TokenType::Tilde,
+                TokenType::LessLess,
+                TokenType::GreaterGreater,
+                TokenType::LessEqual,
+                TokenType::GreaterEqual,
+                TokenType::And,
+                TokenType::Or,
+                TokenType::StarAssign,
+                TokenType::SlashAssign,
+                TokenType::FatArrow,
+            ]
+        );
+
+        // `..` stops a number from taking the dot as a decimal point
+        assert_eq!(
+            types("0..n 1.5..2 x in s"),
+            [
+                TokenType::Literal(Atom::Int(0)),
+                TokenType::DotDot,
+                TokenType::Identifier,
+                TokenType::Literal(Atom::Float(1.5)),
+                TokenType::DotDot,
+                TokenType::Literal(Atom::Int(2)),
+                TokenType::Identifier,
+                TokenType::In,
+                TokenType::Identifier,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_positions() {
+        let positions = |s| {
+            scan_tokens(s)
+                .unwrap()
+                .iter()
+                .map(|t| (t.line, t.col))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            positions("let x = 1.5\n  x += \"é\" # comment\nprint(x)\n"),
+            [
+                (1, 1),
+                (1, 5),
+                (1, 7),
+                (1, 9),
+                (1, 12),
+                (2, 3),
+                (2, 5),
+                (2, 8),
+                (2, 21),
+                (3, 1),
+                (3, 6),
+                (3, 7),
+                (3, 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_large_file() {
+        // a few megabytes, which the old recursive scanner couldn't get through
+        let line = "let x_1 = (y + 2.5) * \"some string\" # and a comment\n";
+        let lines = 100_000;
+        let source = line.repeat(lines);
+        assert!(source.len() > 5_000_000);
+
+        let tokens = scan_tokens(&source).unwrap();
+        assert_eq!(tokens.len(), lines * 11 - 1);
+
+        let last = tokens.last().unwrap();
+        assert_eq!(
+            last.ty,
+            TokenType::Literal(Atom::Str("some string".to_string()))
+        );
+        assert_eq!((last.line, last.col), (lines, 23));
+    }
+
+    #[test]
+    fn test_errors() {
+        use crate::scanner::error::LexError;
+
+        let errors = scan_tokens("let a = 1.2.3 @ b\nlet c = $\nprint(\"oops)\n").unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                LexError::MalformedNumber {
+                    lexeme: "1.2.3".to_string(),
+                    line: 1,
+                    col: 9
+                },
+                LexError::UnexpectedChar {
+                    c: '@',
+                    line: 1,
+                    col: 15
+                },
+                LexError::UnexpectedChar {
+                    c: '$',
+                    line: 2,
+                    col: 9
+                },
+                LexError::UnterminatedString { line: 3, col: 7 },
+            ]
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "Unexpected character '@' on line 1, column 15"
+        );
+    }
+
+    #[test]
+    fn test_escapes() {
+        let strings = |s| {
+            scan_tokens(s)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.lexeme)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            strings(r#""a\tb\n" "\"quoted\" \\ \u{e9}\u{1F600}""#),
+            ["a\tb\n", "\"quoted\" \\ é😀"]
+        );
+        assert_eq!(strings(r#"r"C:\new\table""#), [r"C:\new\table"]);
+        assert_eq!(strings("\"two\nlines\""), ["two\nlines"]);
+
+        // positions after a multi-line string are still right
+        let tokens = scan_tokens("\"a\nb\" x").unwrap();
+        assert_eq!((tokens[1].line, tokens[1].col), (2, 4));
+    }
+
+    #[test]
+    fn test_escape_errors() {
+        use crate::scanner::error::LexError;
+
+        let errors = scan_tokens(r#"print("\q \u{110000} \u{12")"#).unwrap_err();
+        let invalid = |escape: &str, col| LexError::InvalidEscape {
+            escape: escape.to_string(),
+            line: 1,
+            col,
+        };
+        assert_eq!(
+            errors,
+            [
+                invalid(r"\q", 8),
+                invalid(r"\u{110000}", 11),
+                invalid(r"\u{12", 22),
+            ]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            r"Invalid escape sequence \q on line 1, column 8"
         );
+
+        assert_eq!(
+            scan_tokens("r\"abc").unwrap_err(),
+            [LexError::UnterminatedString { line: 1, col: 1 }]
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        use crate::scanner::error::LexError;
+
+        let tokens = scan_tokens("\"x: {x + 1}\" y").unwrap();
+        let expr = |ty, col| Token::new(ty, "".to_string(), 1, col);
+        assert_eq!(
+            tokens[0].ty,
+            TokenType::Interpolation(vec![
+                StrPart::Str("x: ".to_string()),
+                StrPart::Expr(vec![
+                    Token::new(TokenType::Identifier, "x".to_string(), 1, 6),
+                    expr(TokenType::Plus, 8),
+                    Token::new(TokenType::Literal(Atom::Int(1)), "1".to_string(), 1, 10),
+                ]),
+                StrPart::Str("".to_string()),
+            ])
+        );
+        assert_eq!((tokens[0].line, tokens[0].col), (1, 1));
+        assert_eq!(tokens[1].lexeme, "y");
+
+        // the error points at the string, not into the expression
+        assert_eq!(
+            scan_tokens("print(\"abc {a + 1").unwrap_err(),
+            [LexError::UnterminatedString { line: 1, col: 7 }]
+        );
+        // and is only reported once when a nested string is unterminated too
+        assert_eq!(
+            scan_tokens("print(\"{\")").unwrap_err(),
+            [LexError::UnterminatedString { line: 1, col: 7 }]
+        );
+        assert_eq!(
+            scan_tokens("\"{\"{\"").unwrap_err(),
+            [LexError::UnterminatedString { line: 1, col: 1 }]
+        );
+
+        // a brace inside a nested string doesn't end the expression
+        match &scan_tokens("\"{\"a\" + \"}\"}\"").unwrap()[0].ty {
+            TokenType::Interpolation(parts) => match parts.as_slice() {
+                [_, StrPart::Expr(tokens), _] => assert_eq!(
+                    tokens.iter().map(|t| t.ty.clone()).collect::<Vec<_>>(),
+                    [
+                        TokenType::Literal(Atom::Str("a".to_string())),
+                        TokenType::Plus,
+                        TokenType::Literal(Atom::Str("}".to_string())),
+                    ]
+                ),
+                parts => panic!("unexpected parts {:?}", parts),
+            },
+            ty => panic!("expected an interpolation, got {:?}", ty),
+        }
     }
 }
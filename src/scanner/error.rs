@@ -0,0 +1,58 @@
+use std::error::Error;
+use std::fmt;
+
+/// Problems found while scanning. Positions are those of the start of the
+/// offending text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    UnexpectedChar {
+        c: char,
+        line: usize,
+        col: usize,
+    },
+    InvalidEscape {
+        escape: String,
+        line: usize,
+        col: usize,
+    },
+    UnterminatedString {
+        line: usize,
+        col: usize,
+    },
+    MalformedNumber {
+        lexeme: String,
+        line: usize,
+        col: usize,
+    },
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::UnexpectedChar { c, line, col } => {
+                write!(
+                    f,
+                    "Unexpected character {:?} on line {}, column {}",
+                    c, line, col
+                )
+            }
+            LexError::InvalidEscape { escape, line, col } => write!(
+                f,
+                "Invalid escape sequence {} on line {}, column {}",
+                escape, line, col
+            ),
+            LexError::UnterminatedString { line, col } => write!(
+                f,
+                "Unterminated string starting on line {}, column {}",
+                line, col
+            ),
+            LexError::MalformedNumber { lexeme, line, col } => write!(
+                f,
+                "Malformed number {} on line {}, column {}",
+                lexeme, line, col
+            ),
+        }
+    }
+}
+
+impl Error for LexError {}
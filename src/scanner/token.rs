@@ -12,23 +12,37 @@ pub enum TokenType {
     RBrace,
     Comma,
     Dot,
+    DotDot,
     Plus,
     PlusAssign,
     Minus,
     MinusAssign,
     Slash,
+    SlashSlash,
+    SlashAssign,
     Star,
+    StarStar,
+    StarAssign,
     Percent,
+    PercentAssign,
     Bang,
     BangEqual,
     Equal,
     Assign,
+    FatArrow,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
     Identifier,
     Literal(Atom),
+    Interpolation(Vec<StrPart>),
     And,
     Or,
     Struct,
@@ -37,29 +51,47 @@ pub enum TokenType {
     Elif,
     True,
     False,
+    Nil,
     Function,
     For,
+    In,
     While,
     Print,
     EOF,
     Hash,
     Semicolon,
     Break,
+    Yield,
+    Match,
     LBracket,
     RBracket,
     Unknown,
 }
 
+/// A piece of an interpolated string, either literal text or the tokens of
+/// an expression in braces.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StrPart {
+    Str(String),
+    Expr(Vec<Token>),
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub ty: TokenType,
     pub lexeme: String,
     pub line: usize,
+    pub col: usize,
 }
 
 impl Token {
-    pub fn new(ty: TokenType, lexeme: String, line: usize) -> Self {
-        Token { ty, lexeme, line }
+    pub fn new(ty: TokenType, lexeme: String, line: usize, col: usize) -> Self {
+        Token {
+            ty,
+            lexeme,
+            line,
+            col,
+        }
     }
 
     // pub fn unknown(line: usize) -> Self {
@@ -75,6 +107,7 @@ impl Token {
             ty,
             lexeme: "".to_string(),
             line: 0,
+            col: 0,
         }
     }
 }
@@ -2,24 +2,65 @@ use std::collections::BTreeMap;
 
 use crate::{
     block::Block,
-    eval::{atom::Atom, eval_expr},
+    eval::{
+        atom::{Atom, FunctionCall, FunctionData},
+        binary_op,
+        error::{raise, RuntimeError},
+        eval_expr,
+        function::{constants, is_builtin, is_constant},
+        pattern::Pattern,
+    },
     parser::*,
 };
 
+/// The default limit on nested function calls. Each call takes a few
+/// kilobytes of native stack in the tree walker, so this assumes a large
+/// stack like the one `main` runs the interpreter on.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
 #[derive(Debug, Clone)]
 pub struct State {
     pub scopes: Vec<Scope>,
+    pub call_depth: usize,
+    pub max_call_depth: usize,
 }
 
 impl Default for State {
     fn default() -> Self {
-        State {
-            scopes: vec![Scope::default()],
-        }
+        State::with_max_call_depth(DEFAULT_MAX_CALL_DEPTH)
     }
 }
 
 impl State {
+    pub fn with_max_call_depth(max_call_depth: usize) -> Self {
+        let mut globals = Scope::default();
+        for (name, val) in constants() {
+            globals.vars.insert(name.to_string(), val);
+        }
+
+        State {
+            scopes: vec![globals],
+            call_depth: 0,
+            max_call_depth,
+        }
+    }
+
+    /// Called when entering a function, raises a `StackOverflow` if that
+    /// goes past `max_call_depth`. Tail calls don't count.
+    pub fn enter_call(&mut self, function: &str) {
+        if self.call_depth >= self.max_call_depth {
+            raise(RuntimeError::StackOverflow {
+                function: function.to_string(),
+                max_depth: self.max_call_depth,
+            });
+        }
+        self.call_depth += 1;
+    }
+
+    pub fn exit_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
     pub fn get_variable(&self, var: &str) -> Option<&Atom> {
         self.scopes
             .iter()
@@ -30,55 +71,132 @@ impl State {
     fn modify_variable(&mut self, var: &str, val: Atom) {
         // dbg!(self.scopes.clone());
         for scope in &mut self.scopes.iter_mut().rev() {
-            if scope.vars.contains_key(var) {
+            if let Some(old) = scope.vars.get(var) {
+                if val != Atom::Nil {
+                    scope.nil_types.remove(var);
+                } else if *old != Atom::Nil {
+                    scope.nil_types.insert(var.to_string(), old.type_name());
+                }
                 scope.vars.insert(var.to_string(), val);
                 break;
             }
         }
     }
 
+    // the type a nil variable held before it was set to nil, if it ever did
+    fn nil_type(&self, var: &str) -> Option<&'static str> {
+        let scope = self
+            .scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.vars.contains_key(var))?;
+        scope.nil_types.get(var).copied()
+    }
+
     pub fn declare(&mut self, dec: Declaration) {
-        let (val, disc) = {
-            let var = self.get_variable(&dec.lhs);
-            (var.cloned(), var.map(std::mem::discriminant))
-        };
+        if !dec.alias && self.get_variable(&dec.lhs).is_none() {
+            panic!("Uninitialized variable {}", dec.lhs)
+        }
 
-        match (disc, dec.alias) {
-            (_, true) => {
-                let new_val = eval_expr(&dec.rhs, self);
-                self.scopes
-                    .last_mut()
-                    .unwrap()
-                    .vars
-                    .insert(dec.lhs, new_val);
-            }
-            (Some(d), false) => {
-                let rhs_val = eval_expr(&dec.rhs, self);
-                if d == std::mem::discriminant(&&rhs_val) {
-                    // dbg!(dec.lhs.clone(), val.clone());
-                    let new_val = match dec.plus_or_minus {
-                        Some(true) => val.unwrap() + rhs_val,
-                        Some(false) => val.unwrap() - rhs_val,
-                        None => rhs_val,
-                    };
-
-                    // dbg!(new_val.clone());
-                    self.modify_variable(&dec.lhs, new_val);
-                    // dbg!(self.get_variable(&dec.lhs));
-                } else {
-                    panic!("Cannot assign {:?} to {:?}", rhs_val, val);
+        let rhs_val = eval_expr(&dec.rhs, self);
+        if dec.alias {
+            self.define(dec.lhs, rhs_val);
+        } else {
+            self.assign(&dec.lhs, rhs_val, dec.op);
+        }
+    }
+
+    /// Binds the variables in a pattern like `define`, the value has to
+    /// match it.
+    pub fn destructure(&mut self, pattern: &Pattern, val: Atom) {
+        let mut bindings = Vec::new();
+        if !pattern.matches(&val, &mut bindings) {
+            panic!("Can't destructure {} into {}", val, pattern);
+        }
+        for (var, val) in bindings {
+            self.define(var, val);
+        }
+    }
+
+    /// Merges the scopes above `base` into one, inner variables shadowing
+    /// outer ones. Tail calls do this to the caller's scopes rather than
+    /// popping them, since variables are dynamically scoped and the callee
+    /// can still use them, while the number of scopes stays bounded.
+    pub fn collapse_scopes(&mut self, base: usize) {
+        let mut scopes = self.scopes.split_off(base).into_iter();
+        if let Some(mut merged) = scopes.next() {
+            for scope in scopes {
+                for var in scope.vars.keys() {
+                    merged.nil_types.remove(var);
                 }
+                merged.vars.extend(scope.vars);
+                merged.nil_types.extend(scope.nil_types);
             }
-            (None, false) => {
-                panic!("Uninitialized variable {}", dec.lhs)
+            self.scopes.push(merged);
+        }
+    }
+
+    // whether `var` refers to one of the constants every program starts out
+    // with, rather than a local variable shadowing it
+    fn is_global_constant(&self, var: &str) -> bool {
+        is_constant(var) && self.scopes.iter().rposition(|s| s.vars.contains_key(var)) == Some(0)
+    }
+
+    /// Binds a new variable in the innermost scope, shadowing any outer one.
+    /// Constants can be shadowed, but not redeclared in the global scope.
+    /// Calls to a builtin's name always go to the builtin, so a function
+    /// can't take one.
+    pub fn define(&mut self, var: String, val: Atom) {
+        if self.scopes.len() == 1 && is_constant(&var) {
+            panic!("Can't declare {}, it's a constant", var);
+        }
+        if matches!(val, Atom::Function(_)) && is_builtin(&var) {
+            panic!("Can't declare a function named {}, it's a builtin", var);
+        }
+        let scope = self.scopes.last_mut().unwrap();
+        scope.nil_types.remove(&var);
+        scope.vars.insert(var, val);
+    }
+
+    /// Reassigns an existing variable. With an operator, `x op= rhs` is the
+    /// same as `x = x op rhs`. The new value must have the same type as the
+    /// old one, unless either of them is nil. A variable set to nil keeps
+    /// the type it had, only ones declared as nil can take any type.
+    pub fn assign(&mut self, var: &str, rhs_val: Atom, op: Option<Op>) {
+        if self.is_global_constant(var) {
+            panic!("Can't assign to {}, it's a constant", var);
+        }
+        let val = match self.get_variable(var) {
+            Some(val) => val.clone(),
+            None => panic!("Uninitialized variable {}", var),
+        };
+
+        let new_val = match op {
+            Some(op) => binary_op(&op, val.clone(), rhs_val),
+            None => rhs_val,
+        };
+
+        if new_val != Atom::Nil {
+            match self.nil_type(var) {
+                Some(ty) if ty != new_val.type_name() => panic!(
+                    "Cannot assign {:?} to {}, which held {} values before it was set to nil",
+                    new_val, var, ty
+                ),
+                _ if val != Atom::Nil && val.type_name() != new_val.type_name() => {
+                    panic!("Cannot assign {:?} to {:?}", new_val, val)
+                }
+                _ => {}
             }
         }
+        self.modify_variable(var, new_val);
     }
 }
 
 #[derive(Default, Debug, Clone)]
 pub struct Scope {
     pub vars: BTreeMap<String, Atom>,
+    // the types of variables that have been set to nil, see `State::assign`
+    nil_types: BTreeMap<String, &'static str>,
 }
 
 #[derive(Debug, Clone)]
@@ -86,7 +204,8 @@ pub struct Declaration {
     pub lhs: String,
     pub rhs: S,
     pub alias: bool,
-    pub plus_or_minus: Option<bool>,
+    // the operator of a compound assignment like `+=`
+    pub op: Option<Op>,
 }
 
 #[derive(Debug, Clone)]
@@ -102,28 +221,143 @@ pub struct While {
     pub loop_block: Block,
 }
 
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub value: S,
+    pub arms: Vec<Arm>,
+}
+
+/// One `pattern | pattern if guard => body` arm of a `match`. Every
+/// alternative binds the same variables.
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub patterns: Vec<Pattern>,
+    pub guard: Option<S>,
+    pub block: Block,
+}
+
+impl Match {
+    /// Evaluates the value and picks the first arm it matches whose guard
+    /// passes. The arm's bindings are defined in a new scope, which the
+    /// caller pops once it has run the arm's block.
+    pub fn enter_arm(&self, state: &mut State) -> &Block {
+        let val = eval_expr(&self.value, state);
+
+        for arm in &self.arms {
+            let mut bindings = Vec::new();
+            let matched = arm.patterns.iter().any(|pattern| {
+                bindings.clear();
+                pattern.matches(&val, &mut bindings)
+            });
+            if !matched {
+                continue;
+            }
+
+            state.scopes.push(Scope::default());
+            for (var, val) in bindings {
+                state.define(var, val);
+            }
+            match &arm.guard {
+                Some(guard) if eval_expr(guard, state) != Atom::Bool(true) => {
+                    state.scopes.pop();
+                }
+                _ => return &arm.block,
+            }
+        }
+
+        raise(RuntimeError::NoMatch {
+            value: val.to_string(),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Stmt {
     ExprStmt(S),
     PrintStmt(S),
     Dec(Declaration),
+    // a `let` with a pattern on the left, see `State::destructure`
+    Destructure(Pattern, S),
     IfStmt(If),
     WhileStmt(While),
+    MatchStmt(Match),
     Block(Block),
     Break,
+    // only valid in a generator function, see `eval::iterator::Generator`
+    Yield(S),
+}
+
+// A tail call leaves the scopes it was made from for `call_function` to
+// collapse, the callee can still see their variables.
+fn exit_tail_scope(res: Tail, state: &mut State) -> Tail {
+    if let Tail::Value(_) = res {
+        state.scopes.pop();
+    }
+    res
+}
+
+/// The result of running a function body, see `Block::execute_tail`.
+pub enum Tail {
+    Value(Atom),
+    Call(FunctionData, Vec<Atom>),
 }
 
 impl Stmt {
-    pub fn execute(self, state: &mut State) -> Option<Atom> {
+    pub fn execute_tail(&self, state: &mut State) -> Tail {
+        match self {
+            Stmt::ExprStmt(S::Atom(Atom::FnCall(FunctionCall { name, args })))
+                if !is_builtin(name) =>
+            {
+                let fn_data = match state.get_variable(name) {
+                    Some(Atom::Function(fn_data)) => fn_data.clone(),
+                    _ => panic!("Function {} is undefined", name),
+                };
+                let args = args.iter().map(|arg| eval_expr(arg, state)).collect();
+                Tail::Call(fn_data, args)
+            }
+            Stmt::IfStmt(If {
+                cond,
+                then_block,
+                else_block,
+            }) => {
+                let block = if eval_expr(cond, state) == Atom::Bool(true) {
+                    then_block
+                } else {
+                    else_block
+                };
+
+                state.scopes.push(Scope::default());
+                let res = block.execute_tail(state);
+                exit_tail_scope(res, state)
+            }
+            Stmt::MatchStmt(match_data) => {
+                let res = match_data.enter_arm(state).execute_tail(state);
+                exit_tail_scope(res, state)
+            }
+            Stmt::Block(b) => {
+                state.scopes.push(Scope::default());
+                let res = b.execute_tail(state);
+                exit_tail_scope(res, state)
+            }
+            stmt => Tail::Value(stmt.clone().execute(state)),
+        }
+    }
+
+    pub fn execute(self, state: &mut State) -> Atom {
         match self {
-            Stmt::ExprStmt(expr) => Some(eval_expr(&expr, state)),
+            Stmt::ExprStmt(expr) => eval_expr(&expr, state),
             Stmt::PrintStmt(expr) => {
                 println!("{}", eval_expr(&expr, state));
-                None
+                Atom::Nil
             }
             Stmt::Dec(dec) => {
                 state.declare(dec);
-                None
+                Atom::Nil
+            }
+            Stmt::Destructure(pattern, rhs) => {
+                let val = eval_expr(&rhs, state);
+                state.destructure(&pattern, val);
+                Atom::Nil
             }
             Stmt::IfStmt(if_data) => {
                 let If {
@@ -144,20 +378,22 @@ impl Stmt {
                     mut loop_block,
                 } = while_data;
 
-                let mut res = None;
-
                 while eval_expr(&cond, state) == Atom::Bool(true) {
-                    res = loop_block.execute(state);
-                    if matches!(res, Some(Atom::Break)) {
-                        res = None;
+                    if matches!(loop_block.execute(state), Atom::Break) {
                         break;
                     }
                 }
 
+                Atom::Nil
+            }
+            Stmt::MatchStmt(match_data) => {
+                let res = match_data.enter_arm(state).clone().execute_unscoped(state);
+                state.scopes.pop();
                 res
             }
             Stmt::Block(mut b) => b.execute(state),
-            Stmt::Break => Some(Atom::Break),
+            Stmt::Break => Atom::Break,
+            Stmt::Yield(_) => panic!("yield can only be used in a function"),
         }
     }
 }
@@ -167,47 +403,232 @@ mod stmt_tests {
     use crate::run_file;
     use crate::Atom;
     use crate::State;
+    use crate::{Backend, Config};
+
+    fn run_test_file(file: &str, backend: Backend, opt_level: u8) -> Atom {
+        let mut top_state = State::default();
+        let config = Config {
+            backend,
+            opt_level,
+            ..Config::default()
+        };
+        run_file(format!("test_files/{}", file), &mut top_state, &config).unwrap()
+    }
 
+    // every file is run on each backend, with and without optimizations,
+    // e.g. `stmt_tests::basic1::vm_opt1`
     macro_rules! test_files {
         () => {};
+        ( $fn_name:ident, $file:expr => panics $message:expr; $($tail:tt)* ) => {
+            mod $fn_name {
+                use super::*;
+
+                test_variants!(run_test_file, $file, #[should_panic(expected = $message)]);
+            }
+
+            test_files!($($tail)*);
+        };
         ( $fn_name:ident, $file:expr => $expected:expr; $($tail:tt)* ) => {
-            #[test]
-            fn $fn_name() {
-                let mut top_state = State::default();
-                let output = run_file(format!("test_files/{}", $file), &mut top_state).unwrap();
-                assert_eq!(output, $expected);
+            mod $fn_name {
+                use super::*;
+
+                fn check(file: &str, backend: Backend, opt_level: u8) {
+                    assert_eq!(run_test_file(file, backend, opt_level), $expected);
+                }
+
+                test_variants!(check, $file,);
             }
 
             test_files!($($tail)*);
         };
-        ( $fn_name:ident, $file:expr; $($tail:tt)* ) => {
+    }
+
+    macro_rules! test_variants {
+        ( $check:ident, $file:expr, $(#[$attr:meta])? ) => {
             #[test]
-            #[should_panic]
-            fn $fn_name() {
-                let mut top_state = State::default();
-                run_file(format!("test_files/{}", $file), &mut top_state).unwrap();
+            $(#[$attr])?
+            fn tree_walk() {
+                $check($file, Backend::TreeWalk, 0);
             }
 
-            test_files!($($tail)*);
+            #[test]
+            $(#[$attr])?
+            fn vm() {
+                $check($file, Backend::Vm, 0);
+            }
+
+            #[test]
+            $(#[$attr])?
+            fn tree_walk_opt1() {
+                $check($file, Backend::TreeWalk, 1);
+            }
+
+            #[test]
+            $(#[$attr])?
+            fn vm_opt1() {
+                $check($file, Backend::Vm, 1);
+            }
+
+            #[test]
+            $(#[$attr])?
+            fn tree_walk_opt2() {
+                $check($file, Backend::TreeWalk, 2);
+            }
+
+            #[test]
+            $(#[$attr])?
+            fn vm_opt2() {
+                $check($file, Backend::Vm, 2);
+            }
         };
     }
 
     test_files!(
-        basic1, "basic1.slang" => Some(Atom::Int(20));
-        basic2, "basic2.slang" => Some(Atom::Int(5));
-        if1, "if.slang" => Some(Atom::Str("hello".to_string()));
-        if2, "else.slang" => Some(Atom::Str("goodbye".to_string()));
-        scope_modify, "scope_modify.slang" => Some(Atom::Int(2));
-        while1, "while1.slang" => Some(Atom::Int(10));
-        for1, "for1.slang" => Some(Atom::Int(1053));
-        fn1, "fn1.slang" => Some(Atom::Int(120));
-        euler01, "project_euler_01.slang" => Some(Atom::Int(233168));
-        euler02, "project_euler_02.slang" => Some(Atom::Int(4613732));
-        scoped_loop, "scoped_loop.slang" => Some(Atom::Int(45));
-        loop_break, "loop_break.slang" => Some(Atom::Int(5));
-        nested_loop_break, "nested_loop_break.slang" => Some(Atom::Int(25));
-        recur1, "recursion01.slang" => Some(Atom::Int(987));
-        error1, "error1.slang";
-        scope_typecheck, "scope_typecheck.slang";
+        basic1, "basic1.slang" => Atom::Int(20);
+        basic2, "basic2.slang" => Atom::Int(5);
+        if1, "if.slang" => Atom::Str("hello".to_string());
+        if2, "else.slang" => Atom::Str("goodbye".to_string());
+        scope_modify, "scope_modify.slang" => Atom::Int(2);
+        while1, "while1.slang" => Atom::Int(10);
+        for1, "for1.slang" => Atom::Int(1053);
+        fn1, "fn1.slang" => Atom::Int(120);
+        euler01, "project_euler_01.slang" => Atom::Int(233168);
+        euler02, "project_euler_02.slang" => Atom::Int(4613732);
+        euler03, "project_euler_03_test.slang" => Atom::Int(71);
+        euler06, "project_euler_06.slang" => Atom::Int(25164150);
+        scoped_loop, "scoped_loop.slang" => Atom::Int(45);
+        loop_break, "loop_break.slang" => Atom::Int(5);
+        nested_loop_break, "nested_loop_break.slang" => Atom::Int(25);
+        recur1, "recursion01.slang" => Atom::Int(987);
+        tail_recursion, "tail_recursion.slang" => Atom::Int(1000000);
+        alias, "alias.slang" => Atom::Int(5);
+        compound_assign, "compound_assign.slang" => Atom::Str("6 -3 x1".to_string());
+        short_circuit, "short_circuit.slang" => Atom::Str("true false true 4".to_string());
+        strings, "strings.slang" => Atom::Str("=== olléh él true true ===".to_string());
+        string_builtins, "string_builtins.slang" => Atom::Str("3; 4 ;5 12 SLANG LANG 1".to_string());
+        array_builtins, "array_builtins.slang" => Atom::Str("10,9,7,7,2,1 3 1 2 10,9,7,7,2,1".to_string());
+        higher_order, "higher_order.slang" => Atom::Str("21 quick,brown,jumps quick 5 the true true".to_string());
+        for_in, "for_in.slang" => Atom::Str("onetwothree 12 0o1t2t 2 0".to_string());
+        tuples, "tuples.slang" => Atom::Str(
+            "-2 9 12 3 only 30 abbccc 37 (1, one) true (5,) 0".to_string()
+        );
+        fold_errors, "fold_errors.slang" => Atom::Str("ok".to_string());
+        tail_call_scope, "tail_call_scope.slang" => Atom::Str("5 0! 5".to_string());
+        nil, "nil.slang" => Atom::Str("nil true at 1,missing".to_string());
+        match_stmt, "match.slang" => Atom::Str(
+            "zero, small, minus one, negative, greeting, nothing, bool, pair of 3, \
+             pair starting with 1, empty, just 9, 1 and 2 more, big / 111 / +0- / 8"
+                .to_string()
+        );
+        match_expr, "match_expr.slang" => Atom::Str(
+            "minus,zero,plus 7 none 2 40 nil".to_string()
+        );
+        generators, "generators.slang" => Atom::Str(
+            "4613732 2,3,5,7,11,13,17,19,23,29 2 17 false".to_string()
+        );
+        loop_value, "loop_value.slang" => Atom::Str("nil nil nil".to_string());
+        interpolation, "interpolation.slang" => Atom::Str(
+            "circle 3 6 2.5|hello slang! {not interpolated}|5".to_string()
+        );
+        first_class_fn, "first_class_fn.slang" => Atom::Nil;
+        array, "array.slang" => Atom::Nil;
+        euler04, "project_euler_04.slang" => Atom::Nil;
+        error1, "error1.slang" => panics "Cannot assign Str(\"a string\") to Int(10)";
+        scope_typecheck, "scope_typecheck.slang" => panics "Cannot assign Str(\"asdf\") to Int(1)";
+        compound_typecheck, "compound_typecheck.slang" => panics "Cannot assign Float(1.5) to Int(3)";
     );
+
+    #[test]
+    fn not_bool() {
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut top_state = State::default();
+            let config = Config {
+                backend,
+                ..Config::default()
+            };
+            let err = run_file("test_files/not_bool.slang", &mut top_state, &config).unwrap_err();
+            assert_eq!(err.to_string(), "Expected a bool operand for &&, got 5");
+        }
+    }
+
+    #[test]
+    fn integer_overflow() {
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut top_state = State::default();
+            let config = Config {
+                backend,
+                ..Config::default()
+            };
+            let res = run_file("test_files/factorial_overflow.slang", &mut top_state, &config);
+
+            #[cfg(not(feature = "bigint"))]
+            assert_eq!(
+                res.unwrap_err().to_string(),
+                "Integer overflow evaluating 2432902008176640000 * 21"
+            );
+
+            #[cfg(feature = "bigint")]
+            assert_eq!(res.unwrap().to_string(), "15511210043330985984000000");
+        }
+    }
+
+    #[test]
+    fn stack_overflow() {
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut top_state = State::with_max_call_depth(100);
+            let config = Config {
+                backend,
+                ..Config::default()
+            };
+            let err =
+                run_file("test_files/stack_overflow.slang", &mut top_state, &config).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Stack overflow: calling f exceeded the maximum call depth of 100"
+            );
+
+            // the state is usable again afterwards
+            assert_eq!(top_state.scopes.len(), 1);
+            assert_eq!(top_state.call_depth, 0);
+        }
+    }
+
+    #[test]
+    fn nil_keeps_the_type() {
+        // only a variable declared as nil can take any type
+        let code =
+            "let x = nil\nx = \"s\"\nx = nil\nx = \"t\"\nlet y = 1\ny = nil\ny = 2\n\"{x}{y}\"";
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let config = Config {
+                backend,
+                ..Config::default()
+            };
+            let res = crate::run(code, &mut State::default(), false, &config).unwrap();
+            assert_eq!(res, Atom::Str("t2".to_string()));
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Cannot assign Str(\"s\") to x, which held int values before it was set to nil"
+    )]
+    fn nil_doesnt_change_the_type() {
+        let code = "let x = 1\nx = nil\nx = \"s\"";
+        crate::run(code, &mut State::default(), false, &Config::default()).unwrap();
+    }
+
+    #[test]
+    fn tail_calls_dont_overflow() {
+        // the same recursion in tail position only stops when it's told to
+        let code = "fn f(n) { if (n == 1000) { n } else { f(n + 1) } }\nf(0)";
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut top_state = State::with_max_call_depth(100);
+            let config = Config {
+                backend,
+                ..Config::default()
+            };
+            let res = crate::run(code, &mut top_state, false, &config).unwrap();
+            assert_eq!(res, Atom::Int(1000));
+        }
+    }
 }